@@ -0,0 +1,255 @@
+use std::collections::VecDeque;
+
+use bevy::prelude::*;
+use bevy::ui::Val::Px;
+use serde::{Deserialize, Serialize};
+
+use crate::persistence;
+use crate::types::*;
+
+const ACHIEVEMENTS_FILE: &str = "achievements.ron";
+
+const TOAST_WIDTH: f32 = 380.0;
+const TOAST_SLIDE_SECONDS: f32 = 0.3;
+const TOAST_HOLD_SECONDS: f32 = 3.0;
+
+const ACHIEVEMENTS: &[Achievement] = &[
+    Achievement {
+        id: "survive_60",
+        condition: AchievementCondition::SurviveSeconds(60.0),
+    },
+    Achievement {
+        id: "survive_300",
+        condition: AchievementCondition::SurviveSeconds(300.0),
+    },
+    Achievement {
+        id: "enemies_500",
+        condition: AchievementCondition::PeakEnemiesOnScreen(500),
+    },
+    Achievement {
+        id: "chicken",
+        condition: AchievementCondition::SpotChicken,
+    },
+    Achievement {
+        id: "quick_death",
+        condition: AchievementCondition::DieWithinSeconds(5.0),
+    },
+    Achievement {
+        id: "no_thrust",
+        condition: AchievementCondition::NoThrustForSeconds(20.0),
+    },
+    Achievement {
+        id: "chain_5",
+        condition: AchievementCondition::NearMissChain(5),
+    },
+    Achievement {
+        id: "score_1000",
+        condition: AchievementCondition::ReachScore(1000),
+    },
+];
+
+pub struct AchievementPlugin;
+
+impl Plugin for AchievementPlugin {
+    fn build(&self, app: &mut App) {
+        app
+            .add_event::<AchievementUnlockedEvent>()
+            .insert_resource(persistence::load::<UnlockedAchievements>(ACHIEVEMENTS_FILE).unwrap_or_default())
+            .insert_resource(ToastQueue::default())
+            .add_system_set(SystemSet::on_update(AppState::InGame).with_system(check_achievements.label("check_achievements")))
+            .add_system(queue_toasts.after("check_achievements"))
+            .add_system(update_toasts);
+    }
+}
+
+// What has to happen for an achievement to unlock. Checked against the run stats and score as the game plays.
+#[derive(Clone, Copy)]
+enum AchievementCondition {
+    SurviveSeconds(f32),
+    PeakEnemiesOnScreen(u32),
+    SpotChicken,
+    // Checked when the run ends
+    DieWithinSeconds(f32),
+    // Checked when the run ends, the run has to last at least this long
+    NoThrustForSeconds(f32),
+    NearMissChain(u32),
+    ReachScore(u32),
+}
+
+// An unlockable achievement. The full list is ACHIEVEMENTS above.
+struct Achievement {
+    // Stored in the save file, so it shouldn't change once released.
+    // The name and description come from the language files, under achievement.<id>.name and achievement.<id>.description.
+    id: &'static str,
+    condition: AchievementCondition,
+}
+
+// Ids of the achievements the player has unlocked, across all runs
+#[derive(Serialize, Deserialize, Default)]
+struct UnlockedAchievements(Vec<String>);
+
+// Index into the achievement list
+struct AchievementUnlockedEvent(usize);
+
+// The popup shown when an achievement unlocks
+#[derive(Component)]
+struct AchievementToast {
+    age: f32,
+}
+
+// Unlocks that are waiting for the toast in front of them to finish
+#[derive(Default)]
+struct ToastQueue(VecDeque<usize>);
+
+fn condition_met(
+    condition: AchievementCondition,
+    score: &Score,
+    stats: &RunStats,
+    chain: &NearMissChain,
+    run_ended: bool,
+) -> bool {
+    match condition {
+        AchievementCondition::SurviveSeconds(seconds) => score.survival_time >= seconds,
+        AchievementCondition::PeakEnemiesOnScreen(count) => stats.peak_on_screen >= count,
+        AchievementCondition::SpotChicken => stats.chickens_spotted > 0,
+        AchievementCondition::DieWithinSeconds(seconds) => run_ended && score.survival_time < seconds,
+        AchievementCondition::NoThrustForSeconds(seconds) => run_ended && stats.time_thrusting <= 0.0 && score.survival_time >= seconds,
+        AchievementCondition::NearMissChain(count) => chain.count >= count,
+        AchievementCondition::ReachScore(points) => score.points >= points as f32,
+    }
+}
+
+fn check_achievements(
+    player_died: Res<PlayerDied>,
+    score: Res<Score>,
+    stats: Res<RunStats>,
+    chain: Res<NearMissChain>,
+    mut unlocked: ResMut<UnlockedAchievements>,
+    mut unlock_events: EventWriter<AchievementUnlockedEvent>,
+) {
+    let run_ended = player_died.is_changed() && player_died.0;
+    // Nothing changes while dead, apart from the frame the run ends on
+    if player_died.0 && !run_ended {
+        return;
+    }
+
+    let mut any_unlocked = false;
+    for (index, achievement) in ACHIEVEMENTS.iter().enumerate() {
+        if unlocked.0.iter().any(|id| id == achievement.id) {
+            continue;
+        }
+
+        if condition_met(achievement.condition, &score, &stats, &chain, run_ended) {
+            unlocked.0.push(achievement.id.to_string());
+            unlock_events.send(AchievementUnlockedEvent(index));
+            any_unlocked = true;
+        }
+    }
+
+    if any_unlocked {
+        persistence::save(ACHIEVEMENTS_FILE, &*unlocked);
+    }
+}
+
+fn queue_toasts(
+    mut unlock_events: EventReader<AchievementUnlockedEvent>,
+    mut queue: ResMut<ToastQueue>,
+) {
+    for AchievementUnlockedEvent(index) in unlock_events.iter() {
+        queue.0.push_back(*index);
+    }
+}
+
+fn spawn_toast(
+    commands: &mut Commands,
+    font: &Handle<Font>,
+    locale: &Locale,
+    achievement: &Achievement,
+) {
+    let name = locale.get(&format!("achievement.{}.name", achievement.id)).to_string();
+    let description = locale.get(&format!("achievement.{}.description", achievement.id)).to_string();
+
+    commands
+        .spawn_bundle(NodeBundle {
+            style: Style {
+                size: Size::new(Px(TOAST_WIDTH), Val::Auto),
+                position_type: PositionType::Absolute,
+                position: Rect {
+                    top: Px(16.0),
+                    left: Px(-TOAST_WIDTH),
+                    ..Default::default()
+                },
+                padding: Rect::all(Px(12.0)),
+                flex_direction: FlexDirection::ColumnReverse,
+                ..Default::default()
+            },
+            color: Color::rgba(0.1, 0.1, 0.2, 0.9).into(),
+            ..Default::default()
+        })
+        .insert(AchievementToast { age: 0.0 })
+        .with_children(|parent| {
+            parent.spawn_bundle(TextBundle {
+                text: Text::with_section(
+                    locale.format("achievement.unlocked", &[("name", name)]),
+                    TextStyle {
+                        font: font.clone(),
+                        font_size: 24.0,
+                        color: Color::rgb(1.0, 0.8, 0.2),
+                    },
+                    Default::default(),
+                ),
+                ..Default::default()
+            });
+            parent.spawn_bundle(TextBundle {
+                text: Text::with_section(
+                    description,
+                    TextStyle {
+                        font: font.clone(),
+                        font_size: 20.0,
+                        color: Color::WHITE,
+                    },
+                    Default::default(),
+                ),
+                ..Default::default()
+            });
+        });
+}
+
+// One toast at a time slides in from the left, waits, then slides back out
+fn update_toasts(
+    mut commands: Commands,
+    time: Res<Time>,
+    font: Res<BoldFont>,
+    locale: Res<Locale>,
+    mut queue: ResMut<ToastQueue>,
+    mut toast_query: Query<(Entity, &mut AchievementToast, &mut Style)>,
+) {
+    match toast_query.get_single_mut() {
+        Ok((entity, mut toast, mut style)) => {
+            toast.age += time.delta_seconds();
+
+            let out_time = TOAST_SLIDE_SECONDS + TOAST_HOLD_SECONDS;
+            let shown =
+                if toast.age < TOAST_SLIDE_SECONDS {
+                    toast.age / TOAST_SLIDE_SECONDS
+                } else if toast.age < out_time {
+                    1.0
+                } else {
+                    1.0 - (toast.age - out_time) / TOAST_SLIDE_SECONDS
+                };
+
+            if shown <= 0.0 {
+                commands.entity(entity).despawn_recursive();
+            } else {
+                // Ease out so the toast settles into place
+                let eased = 1.0 - (1.0 - shown).powi(2);
+                style.position.left = Px(-TOAST_WIDTH + (TOAST_WIDTH + 16.0) * eased);
+            }
+        }
+        Err(_) => {
+            if let Some(index) = queue.0.pop_front() {
+                spawn_toast(&mut commands, &font.0, &locale, &ACHIEVEMENTS[index]);
+            }
+        }
+    }
+}
@@ -3,35 +3,37 @@ use bevy::ui::Val::Px;
 use heron::prelude::*;
 use rand::Rng;
 
+use crate::scaling::{VIEW_HEIGHT, VIEW_WIDTH};
+use crate::utilities::*;
 use crate::types::*;
 
+// Base asteroid size in world units, before SizeScale
+pub const CHASER_SIZE: f32 = 30.0;
+
 pub struct AsteroidPlugin;
 
 impl Plugin for AsteroidPlugin {
     fn build(&self, app: &mut App) {
         app
-            .add_startup_system(setup)
-            .add_system(spawn_chasers)
-            .add_system(move_chasing_enemies)
-            .add_system(increase_spawn_size);
+            // The enemy count uses BoldFont, which the main setup inserts
+            .add_startup_system_to_stage(StartupStage::PostStartup, setup)
+            .add_system_set(
+                SystemSet::on_update(AppState::InGame)
+                    .with_system(spawn_chasers)
+                    .with_system(move_chasing_enemies)
+                    .with_system(increase_spawn_size)
+            );
     }
 }
 
 fn setup(
     mut commands: Commands, 
     asset_server: Res<AssetServer>,
+    font: Res<BoldFont>,
 ) {
-    commands.insert_resource(SpawnTimer(Timer::from_seconds(0.5, true)));
-    commands.insert_resource(IncreaseSpawnSizeTimer(Timer::from_seconds(5.0, true)));
-    commands.insert_resource(ChaserCount::new(0, 1000));
-    commands.insert_resource(SpawnSizeIncrements(0));
     commands.insert_resource(ChaserSprite(asset_server.load("sprites/Meteor1.png")));
     commands.insert_resource(ChickenSprite(asset_server.load("sprites/Chicken.png")));
 
-    // Loading this in main and here is not ideal, but I don't know the best way to guarantee it is loaded in one place first
-    // I should probably look into a way to do that, but is fine for now.
-    let bold_font: Handle<Font> = asset_server.load("fonts/Fredoka/Fredoka-Bold.ttf");
-
     commands
         .spawn_bundle(NodeBundle {
             style: Style {
@@ -70,7 +72,7 @@ fn setup(
                                     TextSection {
                                         value: "Enemy Count: ".to_string(),
                                         style: TextStyle {
-                                            font: bold_font.clone(),
+                                            font: font.0.clone(),
                                             font_size: 48.0,
                                             color: Color::WHITE,
                                         },
@@ -79,7 +81,7 @@ fn setup(
                                     TextSection {
                                         value: "0".to_string(),
                                         style: TextStyle {
-                                            font: bold_font.clone(),
+                                            font: font.0.clone(),
                                             font_size: 48.0,
                                             color: Color::WHITE,
                                         },
@@ -89,7 +91,8 @@ fn setup(
                             },
                             ..Default::default()
                         })
-                        .insert(EnemyCountText);
+                        .insert(EnemyCountText)
+                        .insert(LocalizedText::new("hud.enemy_count"));
                 });                
         });
 }
@@ -97,10 +100,9 @@ fn setup(
 fn spawn_chasers(
     mut commands: Commands,
     mut timer: ResMut<SpawnTimer>,
-    time: Res<Time>,
+    sim: Res<SimStep>,
     mut chaser_count: ResMut<ChaserCount>,
     mut enemy_count_text_query: Query<&mut Text, With<EnemyCountText>>,
-    windows: Res<Windows>,
     chaser_sprite: Res<ChaserSprite>,
     chicken_sprite: Res<ChickenSprite>,
     mut random_gen: ResMut<RandomGenerator>,
@@ -108,15 +110,15 @@ fn spawn_chasers(
     player_died: Res<PlayerDied>,
     game_paused: ResMut<GamePaused>,
     size_increments: Res<SpawnSizeIncrements>,
+    difficulty: Res<Difficulty>,
 ) {
-    if !game_paused.0 && !player_died.0 && timer.0.tick(time.delta()).just_finished() && !chaser_count.at_max() {
-
-        let window_width = windows.get_primary().unwrap().width();
-        let window_height = windows.get_primary().unwrap().height();
-
-        let size = window_width / 40.;
+    if !game_paused.0 && !player_died.0 && timer.0.tick(sim.delta).just_finished() && !chaser_count.at_max() {
 
-        let player_transform = player_query.single().translation;
+        // In co-op the asteroids come in around both players
+        let player_transform = match center_of(player_query.iter().map(|transform| transform.translation.truncate())) {
+            Some(center) => center,
+            None => return,
+        };
 
         let size_scale =
             if random_gen.0.gen_bool(0.75) { 
@@ -125,84 +127,118 @@ fn spawn_chasers(
                 random_gen.0.gen_range(0.75..2.5 + (size_increments.0 as f32 / 50.))
             };
 
+        // Spawning relative to the fixed view size instead of the window keeps the game the same at any resolution
         let spawn_x: f32 = 
             if random_gen.0.gen_bool(0.5) { 
-                random_gen.0.gen_range((player_transform.x + window_width)..(player_transform.x + window_width + 100.)) 
+                random_gen.0.gen_range((player_transform.x + VIEW_WIDTH)..(player_transform.x + VIEW_WIDTH + 100.)) 
             } else { 
-                random_gen.0.gen_range((player_transform.x - window_width - 100.)..(player_transform.x - window_width)) 
+                random_gen.0.gen_range((player_transform.x - VIEW_WIDTH - 100.)..(player_transform.x - VIEW_WIDTH)) 
             };
 
         let spawn_y: f32 = 
             if random_gen.0.gen_bool(0.5) { 
-                random_gen.0.gen_range((player_transform.y + window_height)..(player_transform.y + window_height + 100.)) 
+                random_gen.0.gen_range((player_transform.y + VIEW_HEIGHT)..(player_transform.y + VIEW_HEIGHT + 100.)) 
             } else {
-                random_gen.0.gen_range((player_transform.y - window_height - 100.)..(player_transform.y - window_height)) 
+                random_gen.0.gen_range((player_transform.y - VIEW_HEIGHT - 100.)..(player_transform.y - VIEW_HEIGHT)) 
             };
 
-        commands
-            .spawn_bundle(
-                SpriteBundle {
-                    texture: if random_gen.0.gen_bool(0.01) { chicken_sprite.0.clone() } else { chaser_sprite.0.clone() },
-                    sprite: Sprite {
-                        custom_size: Some(Vec2::new(size * size_scale, size * size_scale)),
-                        ..Default::default()
-                    },
-                    transform: Transform::from_xyz(spawn_x, spawn_y, 0.0),
-                    ..Default::default()
-                }
-            )
-            .insert(ChasingEnemy)
-            .insert(Speed(2.5))
-            .insert(RigidBody::Dynamic)
-            .insert(SizeScale(size_scale))
-            .insert(CollisionShape::Sphere {
-                radius: (size * size_scale) / 2.,
-            })
-            .insert(Velocity::default())
-            .insert(PhysicMaterial { friction: 1.0, density: 10.0 * size_scale, ..Default::default() })
-            .insert(CollisionLayers::new(Layer::Enemies, Layer::Player).with_mask(Layer::Enemies));
+        let is_chicken = random_gen.0.gen_bool(0.01);
+
+        let texture = if is_chicken { chicken_sprite.0.clone() } else { chaser_sprite.0.clone() };
+        let chaser = spawn_chaser(&mut commands, texture, Vec2::new(spawn_x, spawn_y), size_scale, difficulty.chaser_speed());
+
+        if is_chicken {
+            commands.entity(chaser).insert(Chicken);
+        }
             
             chaser_count.current += 1;
-            enemy_count_text_query.single_mut().sections[1].style.color = Color::Rgba {
-                red: 1.,
-                green: (255. - chaser_count.current as f32) / 255.,
-                blue: (255. - chaser_count.current as f32) / 255.,
-                alpha: 1.
-            };
-            enemy_count_text_query.single_mut().sections[1].value = format!("{:.2}", chaser_count.current);
+            update_enemy_count_text(&mut enemy_count_text_query.single_mut(), chaser_count.current);
     }
 }
 
+// Also used by the dev console to spawn asteroids on demand
+pub fn spawn_chaser(
+    commands: &mut Commands,
+    texture: Handle<Image>,
+    position: Vec2,
+    size_scale: f32,
+    speed: f32,
+) -> Entity {
+    let size = CHASER_SIZE;
+
+    commands
+        .spawn_bundle(
+            SpriteBundle {
+                texture,
+                sprite: Sprite {
+                    custom_size: Some(Vec2::new(size * size_scale, size * size_scale)),
+                    ..Default::default()
+                },
+                transform: Transform::from_xyz(position.x, position.y, 0.0),
+                ..Default::default()
+            }
+        )
+        .insert(ChasingEnemy)
+        .insert(RunScoped)
+        .insert(Speed(speed))
+        .insert(RigidBody::Dynamic)
+        .insert(SizeScale(size_scale))
+        .insert(CollisionShape::Sphere {
+            radius: (size * size_scale) / 2.,
+        })
+        .insert(Velocity::default())
+        .insert(PhysicMaterial { friction: 1.0, density: 10.0 * size_scale, ..Default::default() })
+        .insert(CollisionLayers::new(Layer::Enemies, Layer::Player).with_mask(Layer::Enemies))
+        .id()
+}
+
+// The count goes from white to red as the asteroids pile up
+pub fn update_enemy_count_text(text: &mut Text, count: u32) {
+    text.sections[1].style.color = Color::Rgba {
+        red: 1.,
+        green: (255. - count as f32) / 255.,
+        blue: (255. - count as f32) / 255.,
+        alpha: 1.
+    };
+    text.sections[1].value = format!("{:.2}", count);
+}
+
 fn increase_spawn_size(
     mut increments: ResMut<SpawnSizeIncrements>,
     player_died: Res<PlayerDied>,
     game_paused: ResMut<GamePaused>,
     mut timer: ResMut<IncreaseSpawnSizeTimer>,
-    time: Res<Time>,
+    sim: Res<SimStep>,
 ) {
-    if !game_paused.0 && !player_died.0 && timer.0.tick(time.delta()).just_finished() {
-        if increments.0 < 100 {
-            increments.0 += 1;
-        }
+    if !game_paused.0 && !player_died.0 && timer.0.tick(sim.delta).just_finished() && increments.0 < 100 {
+        increments.0 += 1;
     }
 }
 
 fn move_chasing_enemies(
     game_paused: Res<GamePaused>,
+    sim: Res<SimStep>,
     mut query: Query<(&Transform, &Speed, &mut Velocity), With<ChasingEnemy>>,
-    player_query: Query<&Transform, (With<Player>, Without<ChasingEnemy>)>,
+    player_query: Query<(&Transform, &PlayerHealth), (With<Player>, Without<ChasingEnemy>)>,
 )
 {
-    if !game_paused.0 {
-        if let Some(player_transform) = player_query.iter().next() {
-            for (transform, Speed(speed), mut velocity) in query.iter_mut() {
-                if transform.translation.x > player_transform.translation.x {
+    if !game_paused.0 && !sim.is_stalled() {
+        // Each asteroid goes after whichever living player is closest
+        let living: Vec<Vec2> = player_query
+            .iter()
+            .filter(|(_, health)| health.0 > 0)
+            .map(|(transform, _)| transform.translation.truncate())
+            .collect();
+
+        for (transform, Speed(speed), mut velocity) in query.iter_mut() {
+            if let Some(player_position) = nearest(transform.translation.truncate(), living.iter().copied()) {
+                if transform.translation.x > player_position.x {
                     velocity.linear.x -= speed;
                 } else {
                     velocity.linear.x += speed;
                 }
 
-                if transform.translation.y > player_transform.translation.y {
+                if transform.translation.y > player_position.y {
                     velocity.linear.y -= speed;
                 } else {
                     velocity.linear.y += speed;
@@ -210,4 +246,4 @@ fn move_chasing_enemies(
             }
         }
     }
-}
\ No newline at end of file
+}
@@ -0,0 +1,214 @@
+use bevy::prelude::*;
+use bevy_kira_audio::{Audio, AudioChannel, AudioSource};
+use heron::prelude::*;
+
+use crate::utilities::*;
+use crate::types::*;
+
+// The thruster loops the whole time a key is held, so it sits under the other effects
+const THRUST_VOLUME: f32 = 0.4;
+
+// Music stems from calmest to most intense, with the tension at which each one is fully faded in.
+// They all start together and loop forever, so fading is the only thing that changes.
+const MUSIC_STEMS: [(&str, f32); 3] = [
+    ("audio/music_base.wav", 0.0),
+    ("audio/music_drums.wav", 0.35),
+    ("audio/music_lead.wav", 0.7),
+];
+// Tension range over which a stem fades from silent to full volume
+const STEM_FADE_RANGE: f32 = 0.2;
+// Fraction of the gap to the target volume a stem closes per second
+const STEM_FADE_SPEED: f32 = 1.0;
+
+// bevy_kira_audio only logs a warning when there is no audio device, and every call after that is a no-op,
+// so none of these systems need to check whether audio is actually available.
+pub struct AudioPlugin;
+
+impl Plugin for AudioPlugin {
+    fn build(&self, app: &mut App) {
+        app
+            .add_plugin(bevy_kira_audio::AudioPlugin)
+            // Normally inserted from the saved settings before this plugin is added
+            .init_resource::<AudioVolumes>()
+            .insert_resource(AudioChannels {
+                music_stems: (0..MUSIC_STEMS.len()).map(|i| AudioChannel::new(format!("music_{}", i))).collect(),
+                sfx: AudioChannel::new("sfx".to_string()),
+                thrust: AudioChannel::new("thrust".to_string()),
+            })
+            .insert_resource(StemLevels(vec![0.0; MUSIC_STEMS.len()]))
+            .add_startup_system(setup)
+            .add_system(apply_volumes)
+            .add_system(fade_music_stems.after("update_tension"))
+            .add_system(play_thrust)
+            .add_system(play_hit_sounds)
+            .add_system(play_state_sounds);
+    }
+}
+
+struct AudioChannels {
+    music_stems: Vec<AudioChannel>,
+    sfx: AudioChannel,
+    thrust: AudioChannel,
+}
+
+struct SoundEffects {
+    thrust: Handle<AudioSource>,
+    hit: Handle<AudioSource>,
+    heart_loss: Handle<AudioSource>,
+    death: Handle<AudioSource>,
+    pause: Handle<AudioSource>,
+    restart: Handle<AudioSource>,
+}
+
+// Current fade level of each music stem, before the music volume is applied
+struct StemLevels(Vec<f32>);
+
+// What the game looked like last frame, so sounds only play when something changes
+#[derive(Default)]
+struct PreviousState {
+    thrusting: bool,
+    health: Option<u8>,
+    died: bool,
+    paused: bool,
+}
+
+fn setup(
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+    audio: Res<Audio>,
+    channels: Res<AudioChannels>,
+) {
+    commands.insert_resource(SoundEffects {
+        thrust: asset_server.load("audio/thrust.wav"),
+        hit: asset_server.load("audio/hit.wav"),
+        heart_loss: asset_server.load("audio/heart_loss.wav"),
+        death: asset_server.load("audio/death.wav"),
+        pause: asset_server.load("audio/pause.wav"),
+        restart: asset_server.load("audio/restart.wav"),
+    });
+
+    for ((path, _), channel) in MUSIC_STEMS.iter().zip(channels.music_stems.iter()) {
+        audio.set_volume_in_channel(0.0, channel);
+        audio.play_looped_in_channel(asset_server.load(*path), channel);
+    }
+}
+
+fn stem_target(tension: f32, threshold: f32) -> f32 {
+    if threshold <= 0.0 {
+        1.0
+    } else {
+        ((tension - threshold + STEM_FADE_RANGE) / STEM_FADE_RANGE).clamp(0.0, 1.0)
+    }
+}
+
+fn fade_music_stems(
+    time: Res<Time>,
+    tension: Res<Tension>,
+    volumes: Res<AudioVolumes>,
+    audio: Res<Audio>,
+    channels: Res<AudioChannels>,
+    mut levels: ResMut<StemLevels>,
+) {
+    let blend = (STEM_FADE_SPEED * time.delta_seconds()).min(1.0);
+
+    for (i, (_, threshold)) in MUSIC_STEMS.iter().enumerate() {
+        let target = stem_target(tension.value, *threshold);
+        let level = levels.0[i] + (target - levels.0[i]) * blend;
+
+        // Sending a volume change every frame for every stem is wasteful, so only do it when it is audible
+        if (level - levels.0[i]).abs() > 0.001 || volumes.is_changed() {
+            audio.set_volume_in_channel(level * volumes.music(), &channels.music_stems[i]);
+        }
+        levels.0[i] = level;
+    }
+}
+
+fn apply_volumes(
+    volumes: Res<AudioVolumes>,
+    audio: Res<Audio>,
+    channels: Res<AudioChannels>,
+) {
+    // Music stem volumes are handled by fade_music_stems
+    if volumes.is_changed() {
+        audio.set_volume_in_channel(volumes.sfx(), &channels.sfx);
+        audio.set_volume_in_channel(volumes.sfx() * THRUST_VOLUME, &channels.thrust);
+    }
+}
+
+fn play_thrust(
+    audio: Res<Audio>,
+    channels: Res<AudioChannels>,
+    sounds: Res<SoundEffects>,
+    game_paused: Res<GamePaused>,
+    player_query: Query<&Thrusting, With<Player>>,
+    mut previous: Local<PreviousState>,
+) {
+    // One looping thrust sound covers both players in co-op
+    let thrusting = player_query.iter().any(|thrusting| thrusting.0) && !game_paused.0;
+
+    if thrusting && !previous.thrusting {
+        audio.play_looped_in_channel(sounds.thrust.clone(), &channels.thrust);
+    } else if !thrusting && previous.thrusting {
+        audio.stop_channel(&channels.thrust);
+    }
+    previous.thrusting = thrusting;
+}
+
+fn play_hit_sounds(
+    mut events: EventReader<CollisionEvent>,
+    audio: Res<Audio>,
+    channels: Res<AudioChannels>,
+    sounds: Res<SoundEffects>,
+    health_query: Query<&PlayerHealth>,
+    mut previous: Local<PreviousState>,
+) {
+    // Counted instead of using any() so every event is read and none are left over for next frame
+    let player_hits = events
+        .iter()
+        .filter(|event| {
+            let (layers_1, layers_2) = event.collision_layers();
+            event.is_started() && ((is_player(layers_1) && is_enemy(layers_2)) || (is_player(layers_2) && is_enemy(layers_1)))
+        })
+        .count();
+    if player_hits > 0 {
+        audio.play_in_channel(sounds.hit.clone(), &channels.sfx);
+    }
+
+    // Summed over both players in co-op, so either of them losing a heart is heard
+    let health = health_query.iter().map(|health| health.0).sum::<u8>();
+    if let Some(previous_health) = previous.health {
+        if health < previous_health && health > 0 {
+            audio.play_in_channel(sounds.heart_loss.clone(), &channels.sfx);
+        }
+    }
+    previous.health = Some(health);
+}
+
+fn play_state_sounds(
+    audio: Res<Audio>,
+    channels: Res<AudioChannels>,
+    sounds: Res<SoundEffects>,
+    player_died: Res<PlayerDied>,
+    game_paused: Res<GamePaused>,
+    mut previous: Local<PreviousState>,
+) {
+    if player_died.0 && !previous.died {
+        audio.play_in_channel(sounds.death.clone(), &channels.sfx);
+    } else if !player_died.0 && previous.died {
+        audio.play_in_channel(sounds.restart.clone(), &channels.sfx);
+    }
+
+    if game_paused.0 != previous.paused {
+        audio.play_in_channel(sounds.pause.clone(), &channels.sfx);
+        for channel in channels.music_stems.iter() {
+            if game_paused.0 {
+                audio.pause_channel(channel);
+            } else {
+                audio.resume_channel(channel);
+            }
+        }
+    }
+
+    previous.died = player_died.0;
+    previous.paused = game_paused.0;
+}
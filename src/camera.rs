@@ -0,0 +1,180 @@
+use bevy::prelude::*;
+use heron::prelude::*;
+
+use crate::scaling::{VIEW_HEIGHT, VIEW_WIDTH};
+use crate::utilities::*;
+use crate::types::*;
+
+// How long the camera takes to catch up to its target, in seconds
+const FOLLOW_SMOOTH_TIME: f32 = 0.15;
+const LOOK_AHEAD_SECONDS: f32 = 0.35;
+const MAX_LOOK_AHEAD: f32 = 250.0;
+
+const ZOOM_SMOOTH_TIME: f32 = 0.6;
+const MAX_ZOOM: f32 = 1.6;
+// Player speed at which the speed part of the zoom is maxed out
+const ZOOM_FULL_SPEED: f32 = 1200.0;
+// Number of asteroids near the player at which the density part of the zoom is maxed out
+const ZOOM_FULL_DENSITY: f32 = 40.0;
+// In co-op the camera zooms out to keep both players on screen, with this much room around them, up to MAX_FIT_ZOOM
+const FIT_MARGIN: f32 = 300.0;
+const MAX_FIT_ZOOM: f32 = 4.0;
+
+const TRAUMA_DECAY_PER_SECOND: f32 = 1.2;
+const MAX_SHAKE_OFFSET: f32 = 30.0;
+const MAX_SHAKE_ANGLE: f32 = 0.05;
+
+const HIT_TRAUMA: f32 = 0.6;
+const NEAR_MISS_TRAUMA: f32 = 0.25;
+
+pub struct CameraPlugin;
+
+impl Plugin for CameraPlugin {
+    fn build(&self, app: &mut App) {
+        app
+            .init_resource::<CameraSettings>()
+            .insert_resource(CameraRig::default())
+            .add_system(add_hit_trauma)
+            .add_system(add_near_miss_trauma)
+            .add_system(update_camera.label("update_camera").after("player_movement").after("update_tension"));
+    }
+}
+
+// The camera's unshaken state, kept separately so the shake never feeds back into the follow smoothing
+#[derive(Default)]
+struct CameraRig {
+    position: Vec3,
+    velocity: Vec3,
+    zoom: f32,
+    zoom_velocity: f32,
+    elapsed: f32,
+}
+
+fn add_hit_trauma(
+    mut events: EventReader<CollisionEvent>,
+    mut trauma: ResMut<CameraTrauma>,
+) {
+    for event in events.iter() {
+        if event.is_started() {
+            let (layers_1, layers_2) = event.collision_layers();
+            if (is_player(layers_1) && is_enemy(layers_2)) || (is_player(layers_2) && is_enemy(layers_1)) {
+                trauma.add(HIT_TRAUMA);
+            }
+        }
+    }
+}
+
+fn add_near_miss_trauma(
+    mut events: EventReader<NearMissEvent>,
+    mut trauma: ResMut<CameraTrauma>,
+) {
+    for event in events.iter() {
+        trauma.add(NEAR_MISS_TRAUMA * event.size_scale.min(2.0));
+    }
+}
+
+fn update_camera(
+    time: Res<Time>,
+    game_paused: Res<GamePaused>,
+    settings: Res<CameraSettings>,
+    mut trauma: ResMut<CameraTrauma>,
+    mut rig: ResMut<CameraRig>,
+    tension: Res<Tension>,
+    fit: Res<ViewFit>,
+    player_query: Query<(&Transform, &Velocity, &PlayerHealth), (With<Player>, Without<Camera2D>)>,
+    mut camera_query: Query<(&mut Transform, &mut OrthographicProjection), With<Camera2D>>,
+) {
+    let (mut camera_transform, mut projection) = camera_query.single_mut();
+
+    // Resizing the window while paused (or losing focus, which pauses) still has to refit the view
+    if game_paused.0 {
+        if rig.zoom > 0.0 {
+            fit_projection(&mut projection, rig.zoom, &fit);
+        }
+        return;
+    }
+
+    let delta = time.delta_seconds();
+
+    // Follows the middle of the box around every player, which is just the player outside of co-op
+    let mut min = Vec2::splat(f32::MAX);
+    let mut max = Vec2::splat(f32::MIN);
+    for (transform, _, _) in player_query.iter() {
+        min = min.min(transform.translation.truncate());
+        max = max.max(transform.translation.truncate());
+    }
+    if min.x > max.x {
+        return;
+    }
+    let center = ((min + max) / 2.0).extend(0.0);
+    let spread = max - min;
+
+    // Downed players aren't going anywhere, so only the living ones steer the look ahead and zoom
+    let player_velocity = center_of(
+        player_query
+            .iter()
+            .filter(|(_, _, health)| health.0 > 0)
+            .map(|(_, velocity, _)| velocity.linear.truncate())
+    ).unwrap_or(Vec2::ZERO).extend(0.0);
+
+    if rig.zoom == 0.0 {
+        // First frame, so start on the player instead of sliding in from the origin
+        rig.position = center;
+        rig.zoom = 1.0;
+    }
+
+    let mut target = center;
+    if settings.look_ahead {
+        target += (player_velocity * LOOK_AHEAD_SECONDS).clamp_length_max(MAX_LOOK_AHEAD);
+    }
+    target.z = 0.0;
+
+    if settings.smooth_follow {
+        let (position, mut velocity) = (rig.position, rig.velocity);
+        rig.position = smooth_damp(position, target, &mut velocity, FOLLOW_SMOOTH_TIME, delta);
+        rig.velocity = velocity;
+    } else {
+        rig.position = target;
+        rig.velocity = Vec3::ZERO;
+    }
+
+    let target_zoom =
+        if settings.dynamic_zoom {
+            let speed_factor = (player_velocity.length() / ZOOM_FULL_SPEED).min(1.0);
+            let density_factor = (tension.nearby_enemies as f32 / ZOOM_FULL_DENSITY).min(1.0);
+
+            1.0 + (MAX_ZOOM - 1.0) * speed_factor.max(density_factor)
+        } else {
+            1.0
+        };
+    let fit_zoom = ((spread.x + FIT_MARGIN) / VIEW_WIDTH).max((spread.y + FIT_MARGIN) / VIEW_HEIGHT).min(MAX_FIT_ZOOM);
+    let target_zoom = target_zoom.max(fit_zoom);
+    let (zoom, mut zoom_velocity) = (rig.zoom, rig.zoom_velocity);
+    rig.zoom = smooth_damp_f32(zoom, target_zoom, &mut zoom_velocity, ZOOM_SMOOTH_TIME, delta);
+    rig.zoom_velocity = zoom_velocity;
+
+    trauma.0 = (trauma.0 - TRAUMA_DECAY_PER_SECOND * delta).max(0.0);
+    rig.elapsed += delta;
+
+    // Squaring the trauma makes small bumps subtle and big hits violent
+    let shake = trauma.0 * trauma.0 * settings.shake_scale;
+    let t = rig.elapsed;
+    // Sums of unrelated sine waves are a cheap stand-in for noise, and don't use up numbers from the game's RandomGenerator
+    let offset_x = ((t * 37.0).sin() + (t * 61.3 + 1.7).sin()) * 0.5;
+    let offset_y = ((t * 41.0 + 3.1).sin() + (t * 53.7).sin()) * 0.5;
+    let angle = ((t * 29.0 + 5.3).sin() + (t * 47.9).sin()) * 0.5;
+
+    camera_transform.translation.x = rig.position.x + offset_x * MAX_SHAKE_OFFSET * shake;
+    camera_transform.translation.y = rig.position.y + offset_y * MAX_SHAKE_OFFSET * shake;
+    camera_transform.rotation = Quat::from_rotation_z(angle * MAX_SHAKE_ANGLE * shake);
+
+    fit_projection(&mut projection, rig.zoom, &fit);
+}
+
+// The projection also has to fit the fixed world view to the window
+fn fit_projection(projection: &mut OrthographicProjection, zoom: f32, fit: &ViewFit) {
+    let scale = zoom * fit.world_per_pixel;
+    if (projection.scale - scale).abs() > f32::EPSILON {
+        projection.scale = scale;
+    }
+}
@@ -0,0 +1,351 @@
+use bevy::prelude::*;
+use bevy::ui::Val::Px;
+use heron::prelude::*;
+use rand::SeedableRng;
+
+use crate::asteroids::{spawn_chaser, update_enemy_count_text};
+use crate::player::MAX_HEALTH;
+use crate::types::*;
+
+// Only compiled with `--features dev`. Opened and closed with the backtick key.
+
+const MAX_LOG_LINES: usize = 12;
+const MAX_SPAWN: u32 = 500;
+const SPAWN_RADIUS: f32 = 600.0;
+
+const COMMANDS: [(&str, &str); 10] = [
+    ("help", "list commands"),
+    ("god", "toggle invulnerability"),
+    ("spawn", "spawn <n> [size] - spawn asteroids around the player"),
+    ("clear", "remove every asteroid"),
+    ("heal", "restore full health"),
+    ("kill", "end the run"),
+    ("timescale", "timescale <x> - physics speed multiplier"),
+    ("seed", "seed [n] - show or set the RNG seed"),
+    ("set", "set spawn_interval <seconds>"),
+    ("close", "close the console"),
+];
+
+pub struct ConsolePlugin;
+
+impl Plugin for ConsolePlugin {
+    fn build(&self, app: &mut App) {
+        app
+            .insert_resource(Console::default())
+            // Needs BoldFont, which is only there once the regular startup systems have run
+            .add_startup_system_to_stage(StartupStage::PostStartup, setup)
+            // Runs ahead of everything that reads the keyboard, so typing doesn't also move the ship or press menu buttons
+            .add_system(
+                console_input
+                    .label("console_input")
+                    .before("player_movement")
+                    .before("menu_navigation")
+            )
+            .add_system(run_console_commands.after("console_input"))
+            .add_system(update_console_text.after("console_input"));
+    }
+}
+
+#[derive(Default)]
+struct Console {
+    open: bool,
+    input: String,
+    log: Vec<String>,
+    history: Vec<String>,
+    // Position while stepping back through the history, None when typing a new line
+    history_index: Option<usize>,
+    // Submitted lines waiting to be run
+    pending: Vec<String>,
+}
+
+impl Console {
+    fn print(&mut self, line: impl Into<String>) {
+        self.log.push(line.into());
+        if self.log.len() > MAX_LOG_LINES {
+            let extra = self.log.len() - MAX_LOG_LINES;
+            self.log.drain(..extra);
+        }
+    }
+}
+
+#[derive(Component)]
+struct ConsolePanel;
+
+#[derive(Component)]
+struct ConsoleText;
+
+fn setup(
+    mut commands: Commands,
+    font: Res<BoldFont>,
+) {
+    commands
+        .spawn_bundle(NodeBundle {
+            style: Style {
+                display: Display::None,
+                size: Size::new(Val::Percent(100.0), Val::Auto),
+                position_type: PositionType::Absolute,
+                position: Rect {
+                    top: Px(0.0),
+                    left: Px(0.0),
+                    ..Default::default()
+                },
+                padding: Rect::all(Px(8.0)),
+                ..Default::default()
+            },
+            color: Color::rgba(0.0, 0.0, 0.0, 0.85).into(),
+            ..Default::default()
+        })
+        .insert(ConsolePanel)
+        .with_children(|parent| {
+            parent
+                .spawn_bundle(TextBundle {
+                    text: Text {
+                        sections: vec![
+                            TextSection {
+                                value: "".to_string(),
+                                style: TextStyle {
+                                    font: font.0.clone(),
+                                    font_size: 20.0,
+                                    color: Color::rgb(0.7, 0.7, 0.7),
+                                },
+                            },
+                            TextSection {
+                                value: "> ".to_string(),
+                                style: TextStyle {
+                                    font: font.0.clone(),
+                                    font_size: 20.0,
+                                    color: Color::WHITE,
+                                },
+                            },
+                        ],
+                        ..Default::default()
+                    },
+                    ..Default::default()
+                })
+                .insert(ConsoleText);
+        });
+}
+
+fn autocomplete(console: &mut Console) {
+    // Only the command name is completed, arguments are left alone
+    if console.input.contains(' ') {
+        return;
+    }
+
+    let matches: Vec<&str> = COMMANDS
+        .iter()
+        .map(|(name, _)| *name)
+        .filter(|name| name.starts_with(console.input.as_str()))
+        .collect();
+
+    match matches.as_slice() {
+        [] => {}
+        [only] => console.input = format!("{} ", only),
+        _ => {
+            let options = matches.join("  ");
+            console.print(options);
+        }
+    }
+}
+
+fn console_input(
+    mut keyboard_input: ResMut<Input<KeyCode>>,
+    mut characters: EventReader<ReceivedCharacter>,
+    mut console: ResMut<Console>,
+    mut panel_query: Query<&mut Style, With<ConsolePanel>>,
+) {
+    if keyboard_input.just_pressed(KeyCode::Grave) {
+        console.open = !console.open;
+        panel_query.single_mut().display = if console.open { Display::Flex } else { Display::None };
+    }
+
+    if !console.open {
+        // Drop anything typed while closed so it doesn't show up when the console opens
+        characters.iter().count();
+        return;
+    }
+
+    for event in characters.iter() {
+        if !event.char.is_control() && event.char != '`' {
+            console.input.push(event.char);
+        }
+    }
+
+    if keyboard_input.just_pressed(KeyCode::Back) {
+        console.input.pop();
+    }
+
+    if keyboard_input.just_pressed(KeyCode::Tab) {
+        autocomplete(&mut console);
+    }
+
+    if keyboard_input.just_pressed(KeyCode::Up) && !console.history.is_empty() {
+        let index = match console.history_index {
+            Some(index) => index.saturating_sub(1),
+            None => console.history.len() - 1,
+        };
+        console.history_index = Some(index);
+        console.input = console.history[index].clone();
+    }
+
+    if keyboard_input.just_pressed(KeyCode::Down) {
+        if let Some(index) = console.history_index {
+            if index + 1 < console.history.len() {
+                console.history_index = Some(index + 1);
+                console.input = console.history[index + 1].clone();
+            } else {
+                console.history_index = None;
+                console.input.clear();
+            }
+        }
+    }
+
+    if keyboard_input.just_pressed(KeyCode::Return) {
+        let line = console.input.trim().to_string();
+        console.input.clear();
+        console.history_index = None;
+
+        if !line.is_empty() {
+            if console.history.last() != Some(&line) {
+                console.history.push(line.clone());
+            }
+            console.print(format!("> {}", line));
+            console.pending.push(line);
+        }
+    }
+
+    // Swallow the keyboard while the console is open
+    let pressed: Vec<KeyCode> = keyboard_input.get_pressed().copied().collect();
+    for key in pressed {
+        keyboard_input.reset(key);
+    }
+}
+
+fn parse<T: std::str::FromStr>(console: &mut Console, value: Option<&str>, usage: &str) -> Option<T> {
+    let parsed = value.and_then(|value| value.parse().ok());
+    if parsed.is_none() {
+        console.print(format!("usage: {}", usage));
+    }
+    parsed
+}
+
+fn run_console_commands(
+    mut commands: Commands,
+    mut console: ResMut<Console>,
+    mut god_mode: ResMut<GodMode>,
+    mut physics_time: ResMut<PhysicsTime>,
+    mut spawn_timer: ResMut<SpawnTimer>,
+    mut game_seed: ResMut<GameSeed>,
+    mut random_gen: ResMut<RandomGenerator>,
+    mut chaser_count: ResMut<ChaserCount>,
+    chaser_sprite: Res<ChaserSprite>,
+    difficulty: Res<Difficulty>,
+    mut health_query: Query<&mut PlayerHealth>,
+    player_query: Query<&Transform, With<PlayerOne>>,
+    chaser_query: Query<Entity, With<ChasingEnemy>>,
+    mut enemy_count_text_query: Query<&mut Text, With<EnemyCountText>>,
+) {
+    let pending: Vec<String> = console.pending.drain(..).collect();
+
+    for line in pending {
+        let mut words = line.split_whitespace();
+        let command = words.next().unwrap_or("");
+        let first = words.next();
+        let second = words.next();
+
+        match command {
+            "help" => {
+                for (name, description) in COMMANDS.iter() {
+                    console.print(format!("{} - {}", name, description));
+                }
+            }
+            "god" => {
+                god_mode.0 = !god_mode.0;
+                console.print(format!("god mode {}", if god_mode.0 { "on" } else { "off" }));
+            }
+            "spawn" => {
+                let count = match parse::<u32>(&mut console, first, "spawn <n> [size]") {
+                    Some(count) => count.min(MAX_SPAWN),
+                    None => continue,
+                };
+                let size_scale = second.and_then(|size| size.parse().ok()).unwrap_or(1.0_f32).clamp(0.25, 10.0);
+                let center = player_query.single().translation.truncate();
+
+                // Spread evenly around the player, outside touching distance
+                for i in 0..count {
+                    if chaser_count.at_max() {
+                        break;
+                    }
+                    let angle = i as f32 / count as f32 * std::f32::consts::TAU;
+                    let position = center + Vec2::new(angle.cos(), angle.sin()) * SPAWN_RADIUS;
+                    spawn_chaser(&mut commands, chaser_sprite.0.clone(), position, size_scale, difficulty.chaser_speed());
+                    chaser_count.current += 1;
+                }
+                update_enemy_count_text(&mut enemy_count_text_query.single_mut(), chaser_count.current);
+                console.print(format!("{} asteroids", chaser_count.current));
+            }
+            "clear" => {
+                chaser_query.iter().for_each(|entity| commands.entity(entity).despawn());
+                chaser_count.current = 0;
+                update_enemy_count_text(&mut enemy_count_text_query.single_mut(), 0);
+            }
+            "heal" => {
+                for mut health in health_query.iter_mut() {
+                    health.0 = MAX_HEALTH;
+                }
+            }
+            // calculate_health notices the empty health next frame and ends the run the usual way
+            "kill" => {
+                god_mode.0 = false;
+                for mut health in health_query.iter_mut() {
+                    health.0 = 0;
+                }
+            }
+            "timescale" => {
+                if let Some(scale) = parse::<f32>(&mut console, first, "timescale <x>") {
+                    physics_time.set_scale(scale.max(0.0));
+                }
+            }
+            "seed" => {
+                if let Some(seed) = first.and_then(|seed| seed.parse::<u64>().ok()) {
+                    game_seed.0 = seed;
+                    random_gen.0 = rand_chacha::ChaCha12Rng::seed_from_u64(seed);
+                }
+                console.print(format!("seed {}", game_seed.0));
+            }
+            "set" => match first {
+                Some("spawn_interval") => {
+                    if let Some(seconds) = parse::<f32>(&mut console, second, "set spawn_interval <seconds>") {
+                        let paused = spawn_timer.0.paused();
+                        spawn_timer.0 = Timer::from_seconds(seconds.max(0.01), true);
+                        if paused {
+                            spawn_timer.0.pause();
+                        }
+                    }
+                }
+                _ => console.print("usage: set spawn_interval <seconds>"),
+            },
+            "close" => console.open = false,
+            _ => console.print(format!("unknown command '{}', try help", command)),
+        }
+    }
+}
+
+fn update_console_text(
+    console: Res<Console>,
+    mut panel_query: Query<&mut Style, With<ConsolePanel>>,
+    mut text_query: Query<&mut Text, With<ConsoleText>>,
+) {
+    if !console.is_changed() {
+        return;
+    }
+
+    // The close command has to hide the panel from here
+    if !console.open {
+        panel_query.single_mut().display = Display::None;
+    }
+
+    let mut text = text_query.single_mut();
+    text.sections[0].value = console.log.iter().map(|line| format!("{}\n", line)).collect();
+    text.sections[1].value = format!("> {}_", console.input);
+}
@@ -0,0 +1,357 @@
+use bevy::diagnostic::{Diagnostics, EntityCountDiagnosticsPlugin, FrameTimeDiagnosticsPlugin};
+use bevy::prelude::*;
+use bevy::render::render_resource::{Extent3d, TextureDimension, TextureFormat};
+use bevy::ui::Val::Px;
+use heron::prelude::*;
+
+use crate::utilities::*;
+use crate::types::*;
+
+// Below these the FPS turns yellow, then red
+const GOOD_FPS: f64 = 55.0;
+const OK_FPS: f64 = 30.0;
+
+const RING_TEXTURE_SIZE: u32 = 128;
+const RING_THICKNESS: f32 = 3.0;
+// Velocity lines show where a body will be this many seconds from now
+const VELOCITY_LINE_SECONDS: f32 = 0.25;
+// Width of the velocity lines, in screen pixels
+const VELOCITY_LINE_WIDTH: f32 = 2.0;
+// Above the world and the threat indicators
+const SHAPE_DEBUG_Z: f32 = 0.9;
+
+pub struct DebugPlugin;
+
+impl Plugin for DebugPlugin {
+    fn build(&self, app: &mut App) {
+        app
+            .add_plugin(FrameTimeDiagnosticsPlugin)
+            .add_plugin(EntityCountDiagnosticsPlugin)
+            .insert_resource(ShapeDebugPool::default())
+            .add_startup_system_to_stage(StartupStage::PostStartup, setup)
+            .add_system(toggle_debug_overlay)
+            .add_system(update_fps_text)
+            .add_system(update_debug_text)
+            .add_system(toggle_shape_debug)
+            .add_system(draw_shape_debug.after("update_camera"));
+    }
+}
+
+// The root node of the debug overlay, toggled with F3
+#[derive(Component)]
+struct DebugOverlay;
+
+// The debug overlay's list of counters below the FPS line
+#[derive(Component)]
+struct DebugText;
+
+// Sprites for the collision shape and velocity debug view, reused every frame. F4 turns it on.
+#[derive(Default)]
+struct ShapeDebugPool {
+    enabled: bool,
+    ring_texture: Handle<Image>,
+    rings: Vec<Entity>,
+    lines: Vec<Entity>,
+}
+
+#[derive(Component)]
+struct ShapeDebugSprite;
+
+// White circle outline, tinted per collision layer when drawn
+fn ring_image() -> Image {
+    let size = RING_TEXTURE_SIZE as usize;
+    let radius = size as f32 / 2.;
+    let mut data = Vec::with_capacity(size * size * 4);
+
+    for y in 0..size {
+        for x in 0..size {
+            let distance = Vec2::new(x as f32 + 0.5 - radius, y as f32 + 0.5 - radius).length();
+            let alpha = if distance <= radius && distance >= radius - RING_THICKNESS { 255 } else { 0 };
+            data.extend_from_slice(&[255, 255, 255, alpha]);
+        }
+    }
+
+    Image::new(
+        Extent3d {
+            width: RING_TEXTURE_SIZE,
+            height: RING_TEXTURE_SIZE,
+            depth_or_array_layers: 1,
+        },
+        TextureDimension::D2,
+        data,
+        TextureFormat::Rgba8UnormSrgb,
+    )
+}
+
+fn layer_color(layers: CollisionLayers) -> Color {
+    if is_player(layers) {
+        Color::rgb(0.2, 1.0, 0.3)
+    } else if is_enemy(layers) {
+        Color::rgb(1.0, 0.3, 0.2)
+    } else {
+        Color::WHITE
+    }
+}
+
+fn text_style(font: &Handle<Font>, color: Color) -> TextStyle {
+    TextStyle {
+        font: font.clone(),
+        font_size: 20.0,
+        color,
+    }
+}
+
+fn setup(
+    mut commands: Commands,
+    font: Res<BoldFont>,
+    mut images: ResMut<Assets<Image>>,
+    mut pool: ResMut<ShapeDebugPool>,
+) {
+    pool.ring_texture = images.add(ring_image());
+
+    commands
+        .spawn_bundle(NodeBundle {
+            style: Style {
+                display: Display::None,
+                position_type: PositionType::Absolute,
+                // Below where achievement toasts show up
+                position: Rect {
+                    top: Px(140.0),
+                    left: Px(16.0),
+                    ..Default::default()
+                },
+                padding: Rect::all(Px(8.0)),
+                flex_direction: FlexDirection::ColumnReverse,
+                ..Default::default()
+            },
+            color: Color::rgba(0.0, 0.0, 0.0, 0.6).into(),
+            ..Default::default()
+        })
+        .insert(DebugOverlay)
+        .with_children(|parent| {
+            parent
+                .spawn_bundle(TextBundle {
+                    text: Text {
+                        sections: vec![
+                            TextSection {
+                                value: "FPS: ".to_string(),
+                                style: text_style(&font.0, Color::WHITE),
+                            },
+                            TextSection {
+                                value: "".to_string(),
+                                style: text_style(&font.0, Color::GREEN),
+                            },
+                        ],
+                        ..Default::default()
+                    },
+                    ..Default::default()
+                })
+                .insert(FpsText)
+                .insert(ColorText);
+
+            parent
+                .spawn_bundle(TextBundle {
+                    text: Text::with_section("", text_style(&font.0, Color::WHITE), Default::default()),
+                    ..Default::default()
+                })
+                .insert(DebugText);
+        });
+}
+
+fn toggle_debug_overlay(
+    keyboard_input: Res<Input<KeyCode>>,
+    mut overlay_query: Query<&mut Style, With<DebugOverlay>>,
+) {
+    if keyboard_input.just_pressed(KeyCode::F3) {
+        let mut style = overlay_query.single_mut();
+        style.display =
+            if style.display == Display::None {
+                Display::Flex
+            } else {
+                Display::None
+            };
+    }
+}
+
+fn overlay_visible(overlay_query: &Query<&Style, With<DebugOverlay>>) -> bool {
+    overlay_query.single().display != Display::None
+}
+
+fn update_fps_text(
+    diagnostics: Res<Diagnostics>,
+    overlay_query: Query<&Style, With<DebugOverlay>>,
+    mut text_query: Query<&mut Text, (With<FpsText>, With<ColorText>)>,
+) {
+    if !overlay_visible(&overlay_query) {
+        return;
+    }
+
+    let fps = diagnostics.get(FrameTimeDiagnosticsPlugin::FPS).and_then(|fps| fps.average());
+    let frame_time = diagnostics.get(FrameTimeDiagnosticsPlugin::FRAME_TIME).and_then(|frame_time| frame_time.average());
+
+    if let (Some(fps), Some(frame_time)) = (fps, frame_time) {
+        let mut text = text_query.single_mut();
+        text.sections[1].value = format!("{:.0} ({:.2} ms)", fps, frame_time * 1000.0);
+        text.sections[1].style.color =
+            if fps >= GOOD_FPS {
+                Color::GREEN
+            } else if fps >= OK_FPS {
+                Color::YELLOW
+            } else {
+                Color::RED
+            };
+    }
+}
+
+fn update_debug_text(
+    diagnostics: Res<Diagnostics>,
+    chaser_count: Res<ChaserCount>,
+    size_increments: Res<SpawnSizeIncrements>,
+    spawn_timer: Res<SpawnTimer>,
+    seed: Res<GameSeed>,
+    overlay_query: Query<&Style, With<DebugOverlay>>,
+    body_query: Query<&RigidBody>,
+    player_query: Query<&Velocity, With<PlayerOne>>,
+    mut text_query: Query<&mut Text, With<DebugText>>,
+) {
+    if !overlay_visible(&overlay_query) {
+        return;
+    }
+
+    let entities = diagnostics
+        .get(EntityCountDiagnosticsPlugin::ENTITY_COUNT)
+        .and_then(|entities| entities.value())
+        .unwrap_or(0.0);
+    let velocity = player_query.single().linear;
+
+    text_query.single_mut().sections[0].value = format!(
+        "Entities: {:.0}\nChasers: {} / {}\nPhysics bodies: {}\nSpawn size increments: {}\nSpawn timer: {:.0}%\nSeed: {}\nPlayer velocity: ({:.1}, {:.1})",
+        entities,
+        chaser_count.current,
+        chaser_count.max,
+        body_query.iter().count(),
+        size_increments.0,
+        spawn_timer.0.percent() * 100.0,
+        seed.0,
+        velocity.x,
+        velocity.y,
+    );
+}
+
+fn toggle_shape_debug(
+    keyboard_input: Res<Input<KeyCode>>,
+    mut pool: ResMut<ShapeDebugPool>,
+    mut sprite_query: Query<&mut Visibility, With<ShapeDebugSprite>>,
+) {
+    if keyboard_input.just_pressed(KeyCode::F4) {
+        pool.enabled = !pool.enabled;
+
+        // Hidden once here, draw_shape_debug leaves them alone while it's off
+        if !pool.enabled {
+            for mut visibility in sprite_query.iter_mut() {
+                visibility.is_visible = false;
+            }
+        }
+    }
+}
+
+// Moves the index-th sprite of that kind into place, spawning it if the pool isn't that big yet
+fn place_pooled_sprite(
+    commands: &mut Commands,
+    pool: &mut Vec<Entity>,
+    sprite_query: &mut Query<(&mut Transform, &mut Sprite, &mut Visibility), With<ShapeDebugSprite>>,
+    index: usize,
+    texture: &Handle<Image>,
+    transform: Transform,
+    sprite: Sprite,
+) {
+    if index < pool.len() {
+        if let Ok((mut pooled_transform, mut pooled_sprite, mut visibility)) = sprite_query.get_mut(pool[index]) {
+            *pooled_transform = transform;
+            *pooled_sprite = sprite;
+            visibility.is_visible = true;
+        }
+    } else {
+        pool.push(
+            commands
+                .spawn_bundle(SpriteBundle {
+                    texture: texture.clone(),
+                    transform,
+                    sprite,
+                    ..Default::default()
+                })
+                .insert(ShapeDebugSprite)
+                .id()
+        );
+    }
+}
+
+fn draw_shape_debug(
+    mut commands: Commands,
+    mut pool: ResMut<ShapeDebugPool>,
+    camera_query: Query<&OrthographicProjection, With<Camera2D>>,
+    body_query: Query<(&GlobalTransform, &CollisionShape, &CollisionLayers, Option<&Velocity>)>,
+    mut sprite_query: Query<(&mut Transform, &mut Sprite, &mut Visibility), With<ShapeDebugSprite>>,
+) {
+    if !pool.enabled {
+        return;
+    }
+
+    let pool = &mut *pool;
+    let mut ring_count = 0;
+    let mut line_count = 0;
+    let line_width = VELOCITY_LINE_WIDTH * camera_query.single().scale;
+
+    for (transform, shape, layers, velocity) in body_query.iter() {
+        let position = transform.translation.truncate();
+        let color = layer_color(*layers);
+        let diameter = sphere_radius(shape) * 2.;
+
+        place_pooled_sprite(
+            &mut commands,
+            &mut pool.rings,
+            &mut sprite_query,
+            ring_count,
+            &pool.ring_texture,
+            Transform::from_translation(position.extend(SHAPE_DEBUG_Z)),
+            Sprite {
+                color,
+                custom_size: Some(Vec2::new(diameter, diameter)),
+                ..Default::default()
+            },
+        );
+        ring_count += 1;
+
+        if let Some(velocity) = velocity {
+            let offset = velocity.linear.truncate() * VELOCITY_LINE_SECONDS;
+            if offset.length() < 1.0 {
+                continue;
+            }
+
+            // A thin sprite stretched from the body's center to where it's heading
+            place_pooled_sprite(
+                &mut commands,
+                &mut pool.lines,
+                &mut sprite_query,
+                line_count,
+                &Handle::default(),
+                Transform::from_translation((position + offset / 2.).extend(SHAPE_DEBUG_Z))
+                    .with_rotation(Quat::from_rotation_z(offset.y.atan2(offset.x))),
+                Sprite {
+                    color: Color::YELLOW,
+                    custom_size: Some(Vec2::new(offset.length(), line_width)),
+                    ..Default::default()
+                },
+            );
+            line_count += 1;
+        }
+    }
+
+    for entity in pool.rings.iter().skip(ring_count).chain(pool.lines.iter().skip(line_count)) {
+        if let Ok((_, _, mut visibility)) = sprite_query.get_mut(*entity) {
+            if visibility.is_visible {
+                visibility.is_visible = false;
+            }
+        }
+    }
+}
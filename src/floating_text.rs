@@ -0,0 +1,81 @@
+use bevy::prelude::*;
+
+use crate::types::*;
+
+// How far the text rises over its lifetime, in world units
+const RISE_DISTANCE: f32 = 60.0;
+
+pub struct FloatingTextPlugin;
+
+impl Plugin for FloatingTextPlugin {
+    fn build(&self, app: &mut App) {
+        app
+            .add_system(update_floating_text);
+    }
+}
+
+// World space text that drifts upwards and fades out, like the near miss bonus popups
+#[derive(Component)]
+struct FloatingText {
+    age: f32,
+    lifetime: f32,
+}
+
+pub fn spawn_floating_text(
+    commands: &mut Commands,
+    font: &Handle<Font>,
+    position: Vec3,
+    value: String,
+    color: Color,
+) {
+    commands
+        .spawn_bundle(Text2dBundle {
+            text: Text::with_section(
+                value,
+                TextStyle {
+                    font: font.clone(),
+                    font_size: 32.0,
+                    color,
+                },
+                TextAlignment {
+                    vertical: VerticalAlign::Center,
+                    horizontal: HorizontalAlign::Center,
+                },
+            ),
+            // Above the asteroids and particles, below the indicators
+            transform: Transform::from_xyz(position.x, position.y, 0.3),
+            ..Default::default()
+        })
+        .insert(FloatingText {
+            age: 0.0,
+            lifetime: 1.2,
+        })
+        .insert(RunScoped);
+}
+
+fn update_floating_text(
+    mut commands: Commands,
+    time: Res<Time>,
+    game_paused: Res<GamePaused>,
+    mut text_query: Query<(Entity, &mut FloatingText, &mut Transform, &mut Text)>,
+) {
+    if game_paused.0 {
+        return;
+    }
+
+    for (entity, mut floating, mut transform, mut text) in text_query.iter_mut() {
+        floating.age += time.delta_seconds();
+        if floating.age >= floating.lifetime {
+            commands.entity(entity).despawn();
+            continue;
+        }
+
+        let progress = floating.age / floating.lifetime;
+        transform.translation.y += RISE_DISTANCE / floating.lifetime * time.delta_seconds();
+        // Stays solid for the first half, then fades out
+        let alpha = (2.0 - progress * 2.0).min(1.0);
+        for section in text.sections.iter_mut() {
+            section.style.color.set_a(alpha);
+        }
+    }
+}
@@ -0,0 +1,105 @@
+use bevy::prelude::*;
+
+use crate::menu::*;
+use crate::types::*;
+
+// Gives the death explosion a moment on screen before the summary covers it
+const SCREEN_DELAY_SECONDS: f32 = 1.2;
+
+pub struct GameOverPlugin;
+
+impl Plugin for GameOverPlugin {
+    fn build(&self, app: &mut App) {
+        app
+            .add_system_set(
+                SystemSet::on_update(AppState::InGame)
+                    .with_system(sync_game_over_screen)
+                    .with_system(game_over_actions.after("menu_navigation"))
+            );
+    }
+}
+
+// Marks the summary screen shown after the player dies
+#[derive(Component)]
+struct GameOverScreen;
+
+fn format_time(seconds: f32) -> String {
+    let whole_seconds = seconds as u32;
+    format!("{}:{:02}.{}", whole_seconds / 60, whole_seconds % 60, ((seconds.fract()) * 10.) as u32)
+}
+
+fn sync_game_over_screen(
+    mut commands: Commands,
+    time: Res<Time>,
+    font: Res<BoldFont>,
+    locale: Res<Locale>,
+    player_died: Res<PlayerDied>,
+    score: Res<Score>,
+    stats: Res<RunStats>,
+    seed: Res<GameSeed>,
+    screen_query: Query<Entity, With<GameOverScreen>>,
+    mut time_since_death: Local<f32>,
+) {
+    let open = screen_query.iter().next().is_some();
+
+    if !player_died.0 {
+        *time_since_death = 0.0;
+        for screen in screen_query.iter() {
+            commands.entity(screen).despawn_recursive();
+        }
+        return;
+    }
+
+    *time_since_death += time.delta_seconds();
+    if open || *time_since_death < SCREEN_DELAY_SECONDS {
+        return;
+    }
+
+    let root = spawn_menu_root(&mut commands);
+    commands.entity(root).insert(GameOverScreen).with_children(|parent| {
+        spawn_menu_title(parent, &font.0, &locale, "game_over.title");
+
+        let lines = [
+            ("game_over.survived", format_time(score.survival_time)),
+            ("game_over.score", format!("{}", score.points as u32)),
+            ("game_over.peak_enemies", format!("{}", stats.peak_enemies)),
+            ("game_over.distance", format!("{:.0}", stats.distance)),
+            ("game_over.hits_taken", format!("{}", stats.hits_taken)),
+            ("game_over.near_misses", format!("{}", stats.near_misses)),
+            ("game_over.chickens_spotted", format!("{}", stats.chickens_spotted)),
+            ("game_over.seed", format!("{}", seed.0)),
+        ];
+        for (key, value) in lines {
+            spawn_menu_text(parent, &font.0, &locale.format(key, &[("value", value)]));
+        }
+
+        spawn_localized_button(parent, &font.0, &locale, "menu.restart", 0, MenuAction::Restart, true);
+        spawn_localized_button(parent, &font.0, &locale, "game_over.view_replay", 1, MenuAction::ViewReplay, true);
+        spawn_localized_button(parent, &font.0, &locale, "game_over.main_menu", 2, MenuAction::QuitToMenu, true);
+    });
+}
+
+fn game_over_actions(
+    mut actions: EventReader<MenuActionEvent>,
+    player_died: Res<PlayerDied>,
+    mut state: ResMut<State<AppState>>,
+    mut restart_requests: EventWriter<RestartRequestEvent>,
+) {
+    for MenuActionEvent(action) in actions.iter() {
+        // The pause menu handles these actions while the player is alive
+        if !player_died.0 {
+            continue;
+        }
+
+        match action {
+            MenuAction::Restart => restart_requests.send(RestartRequestEvent),
+            MenuAction::ViewReplay => {
+                let _ = state.push(AppState::Replay);
+            }
+            MenuAction::QuitToMenu | MenuAction::Back => {
+                let _ = state.set(AppState::MainMenu);
+            }
+            _ => {}
+        }
+    }
+}
@@ -0,0 +1,402 @@
+use bevy::prelude::*;
+use bevy::ui::Val::Px;
+use rand::SeedableRng;
+use rand_chacha::ChaCha12Rng;
+
+use crate::persistence;
+use crate::replay::lerp_angle;
+use crate::types::*;
+
+const BEST_GHOST_FILE: &str = "ghost.ron";
+// Bump this whenever GhostRun changes shape or meaning
+const GHOST_VERSION: u32 = 1;
+
+// Runs are compared every this many points. Score goes up with time, so getting to a milestone sooner
+// (through near misses) is what puts a run ahead.
+const MILESTONE_POINTS: f32 = 250.0;
+const DELTA_SECONDS: f32 = 3.0;
+const AHEAD_COLOR: Color = Color::rgb(0.3, 1.0, 0.4);
+const BEHIND_COLOR: Color = Color::rgb(1.0, 0.35, 0.3);
+const GHOST_ALPHA: f32 = 0.35;
+const GHOST_Z: f32 = -0.01;
+
+pub struct GhostPlugin;
+
+impl Plugin for GhostPlugin {
+    fn build(&self, app: &mut App) {
+        app
+            .insert_resource(RacingGhost(false))
+            .insert_resource(RaceProgress::default())
+            // BoldFont is inserted by a startup system, so it can only be used after those have run
+            .add_startup_system_to_stage(StartupStage::PostStartup, setup)
+            .add_system_set(SystemSet::on_update(AppState::MainMenu).with_system(race_ghost.after("menu_navigation")))
+            .add_system_set(
+                SystemSet::on_update(AppState::InGame)
+                    // Reseeds the run, so it has to come after the normal run start
+                    .with_system(start_race.after("start_run"))
+                    .with_system(move_ghost)
+                    .with_system(track_milestones)
+                    .with_system(save_best_ghost)
+            )
+            .add_system_set(SystemSet::on_exit(AppState::InGame).with_system(stop_racing));
+    }
+}
+
+// The translucent earth following a ghost's trail
+#[derive(Component)]
+struct GhostPlayer;
+
+// The translucent ship over the ghost's earth
+#[derive(Component)]
+struct GhostShip;
+
+// Time ahead of or behind the ghost, shown for a few seconds at each score milestone
+#[derive(Component)]
+struct GhostDeltaText;
+
+// Set while the current run is a race against the LoadedGhost
+struct RacingGhost(bool);
+
+#[derive(Default)]
+struct RaceProgress {
+    outlasted_ghost: bool,
+    delta_age: f32,
+}
+
+fn load_ghost(ghost_file: &GhostFile) -> Option<GhostRun> {
+    let ghost: GhostRun = match &ghost_file.0 {
+        Some(path) => persistence::load_path(path)?,
+        None => persistence::load(BEST_GHOST_FILE)?,
+    };
+
+    if ghost.version != GHOST_VERSION {
+        warn!("Ignoring ghost from ghost version {}, expected {}", ghost.version, GHOST_VERSION);
+        return None;
+    }
+    Some(ghost)
+}
+
+fn setup(
+    mut commands: Commands,
+    font: Res<BoldFont>,
+    ghost_file: Res<GhostFile>,
+) {
+    commands.insert_resource(LoadedGhost(load_ghost(&ghost_file)));
+
+    commands
+        .spawn_bundle(NodeBundle {
+            style: Style {
+                size: Size::new(Val::Percent(100.0), Val::Percent(100.0)),
+                position_type: PositionType::Absolute,
+                justify_content: JustifyContent::Center,
+                align_items: AlignItems::FlexEnd,
+                // Just under the hearts
+                padding: Rect {
+                    top: Px(88.0),
+                    ..Default::default()
+                },
+                ..Default::default()
+            },
+            color: Color::NONE.into(),
+            ..Default::default()
+        })
+        .with_children(|parent| {
+            parent
+                .spawn_bundle(TextBundle {
+                    text: Text::with_section(
+                        "",
+                        TextStyle {
+                            font: font.0.clone(),
+                            font_size: 40.0,
+                            color: Color::WHITE,
+                        },
+                        Default::default(),
+                    ),
+                    ..Default::default()
+                })
+                .insert(GhostDeltaText);
+        });
+}
+
+fn race_ghost(
+    mut actions: EventReader<MenuActionEvent>,
+    mut state: ResMut<State<AppState>>,
+    mut difficulty: ResMut<Difficulty>,
+    mut replaced_difficulty: ResMut<ReplacedDifficulty>,
+    mut player_count: ResMut<PlayerCount>,
+    mut racing: ResMut<RacingGhost>,
+    loaded: Res<LoadedGhost>,
+) {
+    for MenuActionEvent(action) in actions.iter() {
+        if *action != MenuAction::RaceGhost {
+            continue;
+        }
+
+        if let Some(ghost) = &loaded.0 {
+            // Same difficulty as the ghost, or the asteroids wouldn't match. The menu's own comes back once the race is over.
+            replaced_difficulty.0.get_or_insert(*difficulty);
+            *difficulty = ghost.difficulty;
+            player_count.0 = 1;
+            racing.0 = true;
+            let _ = state.set(AppState::InGame);
+        }
+    }
+}
+
+fn start_race(
+    mut commands: Commands,
+    mut events: EventReader<StartRunEvent>,
+    racing: Res<RacingGhost>,
+    loaded: Res<LoadedGhost>,
+    player_query: Query<(&Handle<Image>, &Sprite), With<PlayerOne>>,
+    ship_query: Query<(&Handle<TextureAtlas>, &Transform), (With<PlayerShip>, With<PlayerOne>)>,
+    mut text_query: Query<&mut Text, With<GhostDeltaText>>,
+) {
+    if events.iter().count() == 0 {
+        return;
+    }
+
+    commands.insert_resource(RaceProgress::default());
+    text_query.single_mut().sections[0].value.clear();
+
+    let ghost = match (&loaded.0, racing.0) {
+        (Some(ghost), true) => ghost,
+        _ => return,
+    };
+
+    // The ghost's seed brings back the same asteroid spawns
+    commands.insert_resource(GameSeed(ghost.seed));
+    commands.insert_resource(RandomGenerator(ChaCha12Rng::seed_from_u64(ghost.seed)));
+
+    let (earth_texture, earth_sprite) = player_query.single();
+    commands
+        .spawn_bundle(SpriteBundle {
+            texture: earth_texture.clone(),
+            sprite: Sprite {
+                color: Color::rgba(1.0, 1.0, 1.0, GHOST_ALPHA),
+                ..earth_sprite.clone()
+            },
+            // Under the real player but above the stars
+            transform: Transform::from_xyz(0.0, 0.0, GHOST_Z),
+            ..Default::default()
+        })
+        .insert(GhostPlayer)
+        .insert(RunScoped);
+
+    let (ship_atlas, ship_transform) = ship_query.single();
+    commands
+        .spawn_bundle(SpriteSheetBundle {
+            texture_atlas: ship_atlas.clone(),
+            transform: Transform {
+                translation: Vec3::new(0.0, 0.0, GHOST_Z),
+                scale: ship_transform.scale,
+                ..Default::default()
+            },
+            sprite: TextureAtlasSprite {
+                index: 1,
+                color: Color::rgba(1.0, 1.0, 1.0, GHOST_ALPHA),
+                ..Default::default()
+            },
+            ..Default::default()
+        })
+        .insert(GhostShip)
+        .insert(RunScoped);
+}
+
+// Where the ghost was at the given survival time, blended between the recorded points either side of it
+fn trail_point(trail: &[GhostPoint], time: f32) -> Option<(Vec2, f32)> {
+    let last = trail.len().checked_sub(1)?;
+    let next_index = trail.partition_point(|point| point.time <= time).min(last);
+    let index = next_index.saturating_sub(1);
+    let (point, next_point) = (&trail[index], &trail[next_index]);
+    let t =
+        if next_point.time > point.time {
+            ((time - point.time) / (next_point.time - point.time)).clamp(0.0, 1.0)
+        } else {
+            0.0
+        };
+
+    Some((point.position.lerp(next_point.position, t), lerp_angle(point.ship_rotation, next_point.ship_rotation, t)))
+}
+
+// The ghost runs on the same clock as the score, so it waits while the game is paused
+fn move_ghost(
+    score: Res<Score>,
+    loaded: Res<LoadedGhost>,
+    mut player_query: Query<(&mut Transform, &mut Visibility), (With<GhostPlayer>, Without<GhostShip>)>,
+    mut ship_query: Query<(&mut Transform, &mut Visibility), (With<GhostShip>, Without<GhostPlayer>)>,
+) {
+    let ghost = match &loaded.0 {
+        Some(ghost) => ghost,
+        None => return,
+    };
+
+    // Once the ghost's run is over it's gone
+    let alive = score.survival_time <= ghost.survival_time;
+    let point = trail_point(&ghost.trail, score.survival_time);
+
+    for (mut transform, mut visibility) in player_query.iter_mut() {
+        visibility.is_visible = alive;
+        if let Some((position, _)) = point {
+            transform.translation.x = position.x;
+            transform.translation.y = position.y;
+        }
+    }
+    for (mut transform, mut visibility) in ship_query.iter_mut() {
+        visibility.is_visible = alive;
+        if let Some((position, rotation)) = point {
+            transform.translation.x = position.x;
+            transform.translation.y = position.y;
+            transform.rotation = Quat::from_rotation_z(rotation);
+        }
+    }
+}
+
+fn track_milestones(
+    time: Res<Time>,
+    locale: Res<Locale>,
+    score: Res<Score>,
+    player_died: Res<PlayerDied>,
+    racing: Res<RacingGhost>,
+    loaded: Res<LoadedGhost>,
+    mut milestones: ResMut<ScoreMilestones>,
+    mut progress: ResMut<RaceProgress>,
+    mut text_query: Query<&mut Text, With<GhostDeltaText>>,
+) {
+    let mut text = text_query.single_mut();
+
+    progress.delta_age += time.delta_seconds();
+    if progress.delta_age > DELTA_SECONDS && !text.sections[0].value.is_empty() {
+        text.sections[0].value.clear();
+    }
+
+    if player_died.0 {
+        return;
+    }
+
+    let ghost = if racing.0 { loaded.0.as_ref() } else { None };
+    let reached = (score.points / MILESTONE_POINTS) as usize;
+
+    while milestones.0.len() < reached {
+        milestones.0.push(score.survival_time);
+
+        if let Some(ghost) = ghost {
+            let index = milestones.0.len() - 1;
+            // A ghost that never got this far is already outlasted, which gets its own message below
+            if let Some(ghost_time) = ghost.milestones.get(index) {
+                let delta = score.survival_time - ghost_time;
+                text.sections[0].value = locale.get("ghost.delta").replace("{delta}", &format!("{:+.1}", delta));
+                text.sections[0].style.color = if delta <= 0.0 { AHEAD_COLOR } else { BEHIND_COLOR };
+                progress.delta_age = 0.0;
+            }
+        }
+    }
+
+    if let Some(ghost) = ghost {
+        if !progress.outlasted_ghost && score.survival_time > ghost.survival_time {
+            progress.outlasted_ghost = true;
+            text.sections[0].value = locale.get("ghost.outlasted").to_string();
+            text.sections[0].style.color = AHEAD_COLOR;
+            progress.delta_age = 0.0;
+        }
+    }
+}
+
+// Keeps the best single player run as the ghost to race next time
+fn save_best_ghost(
+    player_died: Res<PlayerDied>,
+    player_count: Res<PlayerCount>,
+    sim: Res<SimStep>,
+    difficulty: Res<Difficulty>,
+    game_seed: Res<GameSeed>,
+    score: Res<Score>,
+    recording: Res<RunRecording>,
+    milestones: Res<ScoreMilestones>,
+    ghost_file: Res<GhostFile>,
+    mut loaded: ResMut<LoadedGhost>,
+) {
+    if !player_died.is_changed() || !player_died.0 || player_count.0 != 1 || sim.lockstep {
+        return;
+    }
+
+    let trail: Vec<GhostPoint> = recording.frames
+        .iter()
+        .map(|frame| GhostPoint {
+            time: frame.time,
+            position: frame.player_position,
+            ship_rotation: frame.ship_rotation,
+        })
+        .collect();
+
+    let best_points = match &ghost_file.0 {
+        // Without a ghost file passed in, the loaded ghost is the best run
+        None => loaded.0.as_ref().map(|best| best.points),
+        Some(_) => persistence::load::<GhostRun>(BEST_GHOST_FILE)
+            .filter(|best| best.version == GHOST_VERSION)
+            .map(|best| best.points),
+    };
+    if best_points.is_some_and(|best_points| best_points >= score.points) {
+        return;
+    }
+
+    let ghost = GhostRun {
+        version: GHOST_VERSION,
+        seed: game_seed.0,
+        difficulty: *difficulty,
+        points: score.points,
+        survival_time: score.survival_time,
+        milestones: milestones.0.clone(),
+        trail,
+    };
+    persistence::save(BEST_GHOST_FILE, &ghost);
+
+    // A ghost file passed in stays the one to race
+    if ghost_file.0.is_none() {
+        loaded.0 = Some(ghost);
+    }
+}
+
+fn stop_racing(
+    mut racing: ResMut<RacingGhost>,
+    mut text_query: Query<&mut Text, With<GhostDeltaText>>,
+) {
+    racing.0 = false;
+    text_query.single_mut().sections[0].value.clear();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn point(time: f32, x: f32, ship_rotation: f32) -> GhostPoint {
+        GhostPoint {
+            time,
+            position: Vec2::new(x, 0.0),
+            ship_rotation,
+        }
+    }
+
+    #[test]
+    fn trail_point_is_none_without_a_trail() {
+        assert!(trail_point(&[], 1.0).is_none());
+    }
+
+    #[test]
+    fn trail_point_blends_between_recorded_points() {
+        let trail = [point(0.0, 0.0, 0.0), point(1.0, 10.0, 1.0), point(2.0, 30.0, 1.0)];
+        let (position, rotation) = trail_point(&trail, 0.5).unwrap();
+        assert!((position - Vec2::new(5.0, 0.0)).length() < 1e-4);
+        assert!((rotation - 0.5).abs() < 1e-4);
+
+        let (position, _) = trail_point(&trail, 1.25).unwrap();
+        assert!((position - Vec2::new(15.0, 0.0)).length() < 1e-4);
+    }
+
+    #[test]
+    fn trail_point_holds_the_ends() {
+        let trail = [point(1.0, 5.0, 0.0), point(2.0, 15.0, 0.0)];
+        assert!((trail_point(&trail, 0.0).unwrap().0 - Vec2::new(5.0, 0.0)).length() < 1e-4);
+        assert!((trail_point(&trail, 9.0).unwrap().0 - Vec2::new(15.0, 0.0)).length() < 1e-4);
+        assert!((trail_point(&trail[..1], 9.0).unwrap().0 - Vec2::new(5.0, 0.0)).length() < 1e-4);
+    }
+}
@@ -0,0 +1,148 @@
+use bevy::prelude::*;
+use bevy::{
+    render::render_resource::{Extent3d, TextureDimension, TextureFormat},
+    window::WindowResized,
+};
+use heron::prelude::*;
+
+use crate::asteroids::CHASER_SIZE;
+use crate::utilities::nearest;
+use crate::types::*;
+
+const MAX_INDICATORS: usize = 8;
+const ARROW_TEXTURE_SIZE: u32 = 32;
+// Gap between the arrows and the edge of the window, in pixels
+const EDGE_MARGIN: f32 = 24.0;
+// Asteroids further than this from the edge of the screen are drawn at MIN_ALPHA
+const FADE_DISTANCE: f32 = 1500.0;
+const MIN_ALPHA: f32 = 0.2;
+
+pub struct IndicatorPlugin;
+
+impl Plugin for IndicatorPlugin {
+    fn build(&self, app: &mut App) {
+        app
+            .add_startup_system(setup)
+            .add_system(resize_indicators)
+            .add_system(update_indicators.after("update_camera"));
+    }
+}
+
+// Arrow at the edge of the screen pointing towards an incoming off-screen asteroid
+#[derive(Component)]
+struct ThreatIndicator;
+
+// Half the window size, kept in sync with WindowResized so the arrows hug the edges in fullscreen too
+struct IndicatorBounds(Vec2);
+
+// There's no arrow sprite in the assets, so a plain triangle pointing up is drawn into a texture instead
+fn arrow_image() -> Image {
+    let size = ARROW_TEXTURE_SIZE as usize;
+    let mut data = Vec::with_capacity(size * size * 4);
+
+    // Image rows go from the top down, so the tip of the triangle is in the first row
+    for y in 0..size {
+        let half_width = (y as f32 + 0.5) / size as f32 * (size as f32 / 2.);
+        for x in 0..size {
+            let from_center = (x as f32 + 0.5 - size as f32 / 2.).abs();
+            let alpha = if from_center <= half_width { 255 } else { 0 };
+            data.extend_from_slice(&[255, 255, 255, alpha]);
+        }
+    }
+
+    Image::new(
+        Extent3d {
+            width: ARROW_TEXTURE_SIZE,
+            height: ARROW_TEXTURE_SIZE,
+            depth_or_array_layers: 1,
+        },
+        TextureDimension::D2,
+        data,
+        TextureFormat::Rgba8UnormSrgb,
+    )
+}
+
+fn setup(
+    mut commands: Commands,
+    windows: Res<Windows>,
+    mut images: ResMut<Assets<Image>>,
+) {
+    let window = windows.get_primary().unwrap();
+    commands.insert_resource(IndicatorBounds(Vec2::new(window.width(), window.height()) / 2.));
+
+    let arrow_texture = images.add(arrow_image());
+
+    for _ in 0..MAX_INDICATORS {
+        commands
+            .spawn_bundle(SpriteBundle {
+                texture: arrow_texture.clone(),
+                visibility: Visibility { is_visible: false },
+                ..Default::default()
+            })
+            .insert(ThreatIndicator);
+    }
+}
+
+fn resize_indicators(
+    mut resize_events: EventReader<WindowResized>,
+    mut bounds: ResMut<IndicatorBounds>,
+) {
+    for e in resize_events.iter() {
+        bounds.0 = Vec2::new(e.width, e.height) / 2.;
+    }
+}
+
+fn update_indicators(
+    bounds: Res<IndicatorBounds>,
+    view_fit: Res<ViewFit>,
+    camera_query: Query<(&Transform, &OrthographicProjection), (With<Camera2D>, Without<ThreatIndicator>)>,
+    player_query: Query<&Transform, (With<Player>, Without<ThreatIndicator>)>,
+    chaser_query: Query<(&Transform, &Velocity, &SizeScale), (With<ChasingEnemy>, Without<ThreatIndicator>)>,
+    mut indicator_query: Query<(&mut Transform, &mut Sprite, &mut Visibility), With<ThreatIndicator>>,
+) {
+    let (camera_transform, projection) = camera_query.single();
+    let camera_position = camera_transform.translation.truncate();
+    let player_positions: Vec<Vec2> = player_query.iter().map(|transform| transform.translation.truncate()).collect();
+    let half_view = bounds.0 * projection.scale;
+    let edge = (half_view - Vec2::splat(EDGE_MARGIN * projection.scale)).max(Vec2::ZERO);
+
+    let mut threats: Vec<(f32, Vec2, f32)> = chaser_query
+        .iter()
+        .filter_map(|(transform, velocity, SizeScale(size_scale))| {
+            let position = transform.translation.truncate();
+            let from_camera = position - camera_position;
+            let player_position = nearest(position, player_positions.iter().copied())?;
+            let on_screen = from_camera.x.abs() <= half_view.x && from_camera.y.abs() <= half_view.y;
+            // Only asteroids actually heading for the player are worth a warning
+            let incoming = velocity.linear.truncate().dot(player_position - position) > 0.0;
+
+            if on_screen || !incoming {
+                None
+            } else {
+                Some((position.distance(player_position), from_camera, *size_scale))
+            }
+        })
+        .collect();
+    threats.sort_by(|a, b| a.0.partial_cmp(&b.0).unwrap_or(std::cmp::Ordering::Equal));
+
+    let mut threats = threats.into_iter();
+    for (mut transform, mut sprite, mut visibility) in indicator_query.iter_mut() {
+        if let Some((_, from_camera, size_scale)) = threats.next() {
+            // Shrink the offset until it touches the nearest edge of the view
+            let fit = (edge.x / from_camera.x.abs()).min(edge.y / from_camera.y.abs());
+            let position = camera_position + from_camera * fit;
+            let distance_past_edge = (from_camera - from_camera * fit).length() / projection.scale;
+            let alpha = 1.0 - (1.0 - MIN_ALPHA) * (distance_past_edge / FADE_DISTANCE).min(1.0);
+            // Same size on screen as the asteroid would be at zoom 1.0
+            let size = CHASER_SIZE * size_scale * projection.scale / view_fit.world_per_pixel;
+
+            transform.translation = position.extend(0.5);
+            transform.rotation = Quat::from_rotation_z(from_camera.y.atan2(from_camera.x) - std::f32::consts::FRAC_PI_2);
+            sprite.custom_size = Some(Vec2::new(size, size));
+            sprite.color = Color::rgba(1.0, 0.3, 0.2, alpha);
+            visibility.is_visible = true;
+        } else if visibility.is_visible {
+            visibility.is_visible = false;
+        }
+    }
+}
@@ -0,0 +1,368 @@
+//! Earth Escape as a plugin, so it can be embedded in other apps (like a launcher) and driven from tests.
+//! The `earth_escape` binary is just the default plugin, plus command line options for online play and ghost files.
+
+// Systems get everything they touch as arguments, so long parameter lists and big query types are normal here
+#![allow(clippy::too_many_arguments, clippy::type_complexity)]
+
+use std::path::PathBuf;
+
+use bevy::asset::AssetServerSettings;
+use bevy::window::WindowId;
+use bevy::winit::WinitWindows;
+use winit::window::Icon;
+
+use bevy::{
+    prelude::*, 
+    window::{WindowMode, WindowResizeConstraints},
+};
+use bevy_embedded_assets::EmbeddedAssetPlugin;
+
+use heron::prelude::*;
+
+pub mod types;
+mod player;
+mod utilities;
+mod asteroids;
+mod camera;
+mod starfield;
+mod radar;
+mod indicators;
+mod particles;
+mod audio;
+mod persistence;
+mod tension;
+mod menu;
+mod score;
+mod options;
+mod pause;
+mod stats;
+mod gameover;
+mod replay;
+mod floating_text;
+mod achievements;
+mod localisation;
+mod scaling;
+mod debug;
+mod run;
+mod save;
+mod net;
+mod ghost;
+#[cfg(feature = "dev")]
+mod console;
+
+use types::*;
+pub use player::PlayerPlugin;
+pub use asteroids::AsteroidPlugin;
+use camera::CameraPlugin;
+use starfield::StarfieldPlugin;
+use radar::RadarPlugin;
+use indicators::IndicatorPlugin;
+use particles::ParticlePlugin;
+use audio::AudioPlugin;
+use tension::TensionPlugin;
+use menu::MenuPlugin;
+use score::ScorePlugin;
+use options::OptionsPlugin;
+use pause::PausePlugin;
+use stats::StatsPlugin;
+use gameover::GameOverPlugin;
+use replay::ReplayPlugin;
+use floating_text::FloatingTextPlugin;
+use achievements::AchievementPlugin;
+use localisation::LocalisationPlugin;
+use scaling::ScalingPlugin;
+use debug::DebugPlugin;
+use run::RunPlugin;
+use save::SavePlugin;
+use net::NetPlugin;
+use ghost::GhostPlugin;
+
+/// Where the game's assets (sprites, fonts, sounds and language files) are read from.
+#[derive(Debug, Clone, PartialEq)]
+pub enum AssetSource {
+    /// The `assets` folder baked into the executable at compile time. The default.
+    Embedded,
+    /// A folder on disk, relative to the executable (or to the package root under `cargo run`).
+    Folder(String),
+}
+
+/// The whole game: window, default Bevy plugins, physics and every game plugin.
+///
+/// Made with [`EarthEscapePlugin::builder`], or [`EarthEscapePlugin::default`] for the game as it ships.
+/// Since it adds `DefaultPlugins` itself, it should be the only plugin on its `App`.
+pub struct EarthEscapePlugin {
+    window: Option<WindowDescriptor>,
+    seed: Option<u64>,
+    difficulty: Difficulty,
+    asset_source: AssetSource,
+    network: Option<NetConfig>,
+    ghost_file: Option<PathBuf>,
+}
+
+impl EarthEscapePlugin {
+    /// Starts from the same options as the standalone game.
+    pub fn builder() -> EarthEscapeBuilder {
+        EarthEscapeBuilder::default()
+    }
+}
+
+impl Default for EarthEscapePlugin {
+    fn default() -> Self {
+        EarthEscapePlugin::builder().build()
+    }
+}
+
+/// Options for [`EarthEscapePlugin`]. Anything left unset behaves the same as the standalone game.
+pub struct EarthEscapeBuilder {
+    window: Option<WindowDescriptor>,
+    seed: Option<u64>,
+    difficulty: Difficulty,
+    asset_source: AssetSource,
+    network: Option<NetConfig>,
+    ghost_file: Option<PathBuf>,
+}
+
+impl Default for EarthEscapeBuilder {
+    fn default() -> Self {
+        EarthEscapeBuilder {
+            window: None,
+            seed: None,
+            difficulty: Difficulty::Normal,
+            asset_source: AssetSource::Embedded,
+            network: None,
+            ghost_file: None,
+        }
+    }
+}
+
+impl EarthEscapeBuilder {
+    /// Replaces the window built from the player's saved settings.
+    pub fn window(mut self, window: WindowDescriptor) -> Self {
+        self.window = Some(window);
+        self
+    }
+
+    /// Uses the same seed for every run instead of a random one, so asteroid spawns repeat exactly.
+    pub fn seed(mut self, seed: u64) -> Self {
+        self.seed = Some(seed);
+        self
+    }
+
+    /// The difficulty selected when the game starts. Players can still change it on the main menu.
+    pub fn difficulty(mut self, difficulty: Difficulty) -> Self {
+        self.difficulty = difficulty;
+        self
+    }
+
+    /// Where assets are loaded from. Defaults to the copy embedded in the executable.
+    pub fn asset_source(mut self, asset_source: AssetSource) -> Self {
+        self.asset_source = asset_source;
+        self
+    }
+
+    /// Hosts or joins an online co-op game as soon as the other player shows up. The main menu still works while waiting.
+    pub fn network(mut self, network: NetConfig) -> Self {
+        self.network = Some(network);
+        self
+    }
+
+    /// Races against the ghost in this file (like one a friend sent over) instead of the player's own best run.
+    pub fn ghost_file(mut self, path: impl Into<PathBuf>) -> Self {
+        self.ghost_file = Some(path.into());
+        self
+    }
+
+    /// Finishes the builder. Add the returned plugin to an empty `App` and run it to start the game.
+    pub fn build(self) -> EarthEscapePlugin {
+        EarthEscapePlugin {
+            window: self.window,
+            seed: self.seed,
+            difficulty: self.difficulty,
+            asset_source: self.asset_source,
+            network: self.network,
+            ghost_file: self.ghost_file,
+        }
+    }
+}
+
+impl Plugin for EarthEscapePlugin {
+    fn build(&self, app: &mut App) {
+        // Settings have to be read before DefaultPlugins is added, since that is when the window gets created
+        let settings = options::load_settings();
+
+        let window = self.window.clone().unwrap_or_else(|| WindowDescriptor {
+            transparent: false,
+            decorations: true,
+            mode: if settings.fullscreen { WindowMode::BorderlessFullscreen } else { WindowMode::Windowed },
+            title: "Earth Escape".to_string(),
+            width: settings.window_width,
+            height: settings.window_height,
+            resize_constraints: WindowResizeConstraints {
+                min_height: 400.0,
+                min_width: 400.0,
+                ..Default::default()
+            },
+            ..Default::default()
+        });
+
+        app
+            .insert_resource(ClearColor(Color::rgba(0.0, 0.0, 0.0, 1.0)))
+            .insert_resource(window);
+
+        match &self.asset_source {
+            AssetSource::Embedded => {
+                app.add_plugins_with(DefaultPlugins, |group| {
+                    group.add_before::<bevy::asset::AssetPlugin, _>(EmbeddedAssetPlugin)
+                });
+            }
+            AssetSource::Folder(folder) => {
+                app
+                    .insert_resource(AssetServerSettings { asset_folder: folder.clone() })
+                    .add_plugins(DefaultPlugins);
+            }
+        }
+
+        app
+            .insert_resource(settings.volumes)
+            .insert_resource(settings.camera)
+            .insert_resource(settings.controls)
+            .insert_resource(settings)
+            .insert_resource(self.difficulty)
+            .insert_resource(SeedOverride(self.seed))
+            .insert_resource(GhostFile(self.ghost_file.clone()))
+            .add_state(AppState::MainMenu)
+            .add_plugin(PhysicsPlugin::default())
+            .add_plugin(RunPlugin)
+            .add_plugin(SavePlugin)
+            .add_plugin(PlayerPlugin)
+            .add_plugin(AsteroidPlugin)
+            .add_plugin(CameraPlugin)
+            .add_plugin(StarfieldPlugin)
+            .add_plugin(RadarPlugin)
+            .add_plugin(IndicatorPlugin)
+            .add_plugin(ParticlePlugin)
+            .add_plugin(AudioPlugin)
+            .add_plugin(TensionPlugin)
+            .add_plugin(MenuPlugin)
+            .add_plugin(ScorePlugin)
+            .add_plugin(OptionsPlugin)
+            .add_plugin(PausePlugin)
+            .add_plugin(StatsPlugin)
+            .add_plugin(GameOverPlugin)
+            .add_plugin(ReplayPlugin)
+            .add_plugin(GhostPlugin)
+            .add_plugin(FloatingTextPlugin)
+            .add_plugin(AchievementPlugin)
+            .add_plugin(LocalisationPlugin)
+            .add_plugin(ScalingPlugin)
+            .add_plugin(DebugPlugin)
+            .add_startup_system(set_window_icon)
+            .add_startup_system(setup)
+            .add_system(fullscreen_toggle);
+
+        if let Some(network) = &self.network {
+            app.add_plugin(NetPlugin(network.clone()));
+        }
+
+        // Cheat console, only built with `cargo run --features dev`
+        #[cfg(feature = "dev")]
+        app.add_plugin(console::ConsolePlugin);
+    }
+}
+
+// The options plugin does the actual switch when it sees the settings change
+fn fullscreen_toggle(keyboard_input: Res<Input<KeyCode>>, mut settings: ResMut<Settings>) {
+    if keyboard_input.just_pressed(KeyCode::F11) {
+        settings.fullscreen = !settings.fullscreen;
+    }
+}
+
+fn setup(
+    mut commands: Commands, 
+    asset_server: Res<AssetServer>,
+) {
+    // UI camera
+    commands.spawn_bundle(UiCameraBundle::default());
+    commands.spawn_bundle(OrthographicCameraBundle::new_2d()).insert(Camera2D);
+    commands.insert_resource(PhysicsTime::new(1.0));
+
+    let bold_font: Handle<Font> = asset_server.load("fonts/Fredoka/Fredoka-Bold.ttf");
+    commands.insert_resource(BoldFont(bold_font.clone()));
+
+    commands
+        .spawn_bundle(NodeBundle {
+            style: Style {
+                size: Size::new(Val::Percent(100.0), Val::Percent(100.0)),
+                position_type: PositionType::Absolute,
+                justify_content: JustifyContent::Center,
+                align_items: AlignItems::Center,
+                flex_direction: FlexDirection::ColumnReverse,
+                ..Default::default()
+            },
+            color: Color::NONE.into(),
+            ..Default::default()
+        })
+        .with_children(|parent| {
+            parent
+                .spawn_bundle(TextBundle {
+                    // Use `Text` directly
+                    text: Text {
+                        // Construct a `Vec` of `TextSection`s
+                        sections: vec![
+                            TextSection {
+                                value: "".to_string(),
+                                style: TextStyle {
+                                    font: bold_font.clone(),
+                                    font_size: 82.0,
+                                    color: Color::WHITE,
+                                },
+                            },
+                        ],
+                        ..Default::default()
+                    },
+                ..Default::default()
+            })
+            .insert(CenterMessageText);
+            
+                parent
+                .spawn_bundle(TextBundle {
+                    // Use `Text` directly
+                    text: Text {
+                        // Construct a `Vec` of `TextSection`s
+                        sections: vec![
+                            TextSection {
+                                value: "".to_string(),
+                                style: TextStyle {
+                                    font: bold_font.clone(),
+                                    font_size: 36.0,
+                                    color: Color::GREEN,
+                                },
+                            },
+                        ],
+                        ..Default::default()
+                    },
+                ..Default::default()
+            })
+            .insert(SubCenterText);
+        });
+}
+
+// This should behave on non-Windows builds, but it might need to be removed/refactored for those.
+// Comes from https://bevy-cheatbook.github.io/cookbook/window-icon.html
+// Should be updated to use the correct Bevy method whenever that is released
+fn set_window_icon(
+    windows: Res<WinitWindows>,
+) {
+    let primary = windows.get_window(WindowId::primary()).unwrap();
+
+    if let Ok(base_image) = image::open("assets/sprites/Chicken.png") {
+        let image = base_image.into_rgba8();
+
+        let (width, height) = image.dimensions();
+        let rgba = image.into_raw();
+
+        let icon = Icon::from_rgba(rgba, width, height).unwrap();
+
+        primary.set_window_icon(Some(icon));
+    }
+}
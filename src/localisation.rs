@@ -0,0 +1,167 @@
+use std::collections::HashMap;
+
+use bevy::asset::{AssetLoader, LoadContext, LoadedAsset};
+use bevy::prelude::*;
+use bevy::reflect::TypeUuid;
+use bevy::utils::BoxedFuture;
+use serde::Deserialize;
+
+use crate::types::*;
+
+// Used for every language that doesn't name its own font
+const DEFAULT_FONT: &str = "fonts/Fredoka/Fredoka-Bold.ttf";
+
+pub struct LocalisationPlugin;
+
+impl Plugin for LocalisationPlugin {
+    fn build(&self, app: &mut App) {
+        app
+            .add_asset::<LanguageFile>()
+            .init_asset_loader::<LanguageFileLoader>()
+            .insert_resource(Locale::default())
+            .add_startup_system(setup)
+            .add_system(load_language.label("load_language"))
+            .add_system(update_locale.label("update_locale").after("load_language"))
+            .add_system(apply_font.after("update_locale"))
+            .add_system(update_localized_text.after("update_locale"));
+    }
+}
+
+// A translation table, loaded from a .lang file (RON) by the localisation plugin
+#[derive(Deserialize, TypeUuid)]
+#[uuid = "6f2b8d4e-3c1a-4e57-9b0d-2a8c5f7e1d39"]
+struct LanguageFile {
+    // Font to use instead of Fredoka, for scripts it doesn't have glyphs for
+    #[serde(default)]
+    font: Option<String>,
+    strings: HashMap<String, String>,
+}
+
+#[derive(Default)]
+pub struct LanguageFileLoader;
+
+impl AssetLoader for LanguageFileLoader {
+    fn load<'a>(
+        &'a self,
+        bytes: &'a [u8],
+        load_context: &'a mut LoadContext,
+    ) -> BoxedFuture<'a, Result<(), anyhow::Error>> {
+        Box::pin(async move {
+            let language_file: LanguageFile = ron::de::from_bytes(bytes)?;
+            load_context.set_default_asset(LoadedAsset::new(language_file));
+            Ok(())
+        })
+    }
+
+    fn extensions(&self) -> &[&str] {
+        &["lang"]
+    }
+}
+
+// English is always kept loaded to fill in any strings a translation is missing
+struct LanguageHandles {
+    language: Language,
+    current: Handle<LanguageFile>,
+    fallback: Handle<LanguageFile>,
+}
+
+fn language_path(language: Language) -> String {
+    format!("lang/{}.lang", language.code())
+}
+
+fn setup(
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+    settings: Res<Settings>,
+) {
+    commands.insert_resource(LanguageHandles {
+        language: settings.language,
+        current: asset_server.load(language_path(settings.language).as_str()),
+        fallback: asset_server.load(language_path(Language::English).as_str()),
+    });
+}
+
+fn load_language(
+    asset_server: Res<AssetServer>,
+    settings: Res<Settings>,
+    mut handles: ResMut<LanguageHandles>,
+) {
+    if settings.is_changed() && settings.language != handles.language {
+        handles.language = settings.language;
+        handles.current = asset_server.load(language_path(settings.language).as_str());
+    }
+}
+
+// Rebuilds the locale whenever either language file finishes loading (or is edited, with the file watcher on),
+// and whenever the language is switched to one that is already loaded
+fn update_locale(
+    mut events: EventReader<AssetEvent<LanguageFile>>,
+    asset_server: Res<AssetServer>,
+    language_files: Res<Assets<LanguageFile>>,
+    handles: Res<LanguageHandles>,
+    mut locale: ResMut<Locale>,
+    mut font: ResMut<BoldFont>,
+) {
+    let files_changed = events
+        .iter()
+        .filter(|event| match event {
+            AssetEvent::Created { handle } | AssetEvent::Modified { handle } => *handle == handles.current || *handle == handles.fallback,
+            AssetEvent::Removed { .. } => false,
+        })
+        .count() > 0;
+
+    if !files_changed && !handles.is_changed() {
+        return;
+    }
+
+    let current = match language_files.get(&handles.current) {
+        Some(current) => current,
+        // Keeps showing the old language until the new one has loaded
+        None => return,
+    };
+
+    locale.strings = current.strings.clone();
+    if let Some(fallback) = language_files.get(&handles.fallback) {
+        locale.fallback = fallback.strings.clone();
+    }
+
+    let font_path = current.font.as_deref().unwrap_or(DEFAULT_FONT);
+    let wanted_font: Handle<Font> = asset_server.load(font_path);
+    if font.0 != wanted_font {
+        font.0 = wanted_font;
+    }
+}
+
+// Every bit of text in the game uses the one bold font, so switching it means swapping the font on all of them
+fn apply_font(
+    font: Res<BoldFont>,
+    mut text_query: Query<&mut Text>,
+) {
+    if !font.is_changed() || font.is_added() {
+        return;
+    }
+
+    for mut text in text_query.iter_mut() {
+        for section in text.sections.iter_mut() {
+            section.style.font = font.0.clone();
+        }
+    }
+}
+
+fn update_localized_text(
+    locale: Res<Locale>,
+    mut text_query: QuerySet<(
+        QueryState<(&mut Text, &LocalizedText)>,
+        QueryState<(&mut Text, &LocalizedText), Added<LocalizedText>>,
+    )>,
+) {
+    if locale.is_changed() {
+        for (mut text, localized) in text_query.q0().iter_mut() {
+            text.sections[localized.section].value = locale.get(localized.key).to_string();
+        }
+    } else {
+        for (mut text, localized) in text_query.q1().iter_mut() {
+            text.sections[localized.section].value = locale.get(localized.key).to_string();
+        }
+    }
+}
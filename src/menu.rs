@@ -0,0 +1,393 @@
+use bevy::prelude::*;
+use bevy::app::AppExit;
+use bevy::ui::Val::Px;
+use heron::prelude::*;
+
+use crate::save::load_saved_run;
+use crate::types::*;
+
+const BUTTON_COLOR: Color = Color::rgb(0.15, 0.15, 0.25);
+const FOCUSED_BUTTON_COLOR: Color = Color::rgb(0.35, 0.35, 0.6);
+const DISABLED_BUTTON_COLOR: Color = Color::rgb(0.1, 0.1, 0.1);
+const DISABLED_TEXT_COLOR: Color = Color::rgb(0.4, 0.4, 0.4);
+const BACKGROUND_COLOR: Color = Color::rgba(0.0, 0.0, 0.0, 0.75);
+// Size at the reference resolution, the scaling plugin resizes buttons along with the rest of the HUD
+pub const BUTTON_WIDTH: f32 = 360.0;
+pub const BUTTON_HEIGHT: f32 = 60.0;
+
+// Gamepads are polled by index since there's no list of the connected ones to go through
+const MAX_GAMEPADS: usize = 4;
+
+pub struct MenuPlugin;
+
+impl Plugin for MenuPlugin {
+    fn build(&self, app: &mut App) {
+        app
+            .insert_resource(MenuFocus(0))
+            .add_event::<MenuActionEvent>()
+            .add_system(reset_menu_focus.label("reset_menu_focus"))
+            .add_system(menu_navigation.label("menu_navigation").after("reset_menu_focus"))
+            .add_system(menu_button_colors.after("menu_navigation"))
+            .add_system(update_difficulty_label)
+            .add_system_set(
+                SystemSet::on_enter(AppState::MainMenu)
+                    .with_system(enter_main_menu)
+                    .with_system(spawn_main_menu)
+            )
+            .add_system_set(SystemSet::on_resume(AppState::MainMenu).with_system(spawn_main_menu))
+            .add_system_set(SystemSet::on_update(AppState::MainMenu).with_system(main_menu_actions.after("menu_navigation")))
+            .add_system_set(SystemSet::on_pause(AppState::MainMenu).with_system(despawn_menu_screens))
+            .add_system_set(SystemSet::on_exit(AppState::MainMenu).with_system(despawn_menu_screens))
+            .add_system_set(SystemSet::on_enter(AppState::HighScores).with_system(spawn_high_scores))
+            .add_system_set(SystemSet::on_update(AppState::HighScores).with_system(high_score_actions.after("menu_navigation")))
+            .add_system_set(SystemSet::on_exit(AppState::HighScores).with_system(despawn_menu_screens));
+    }
+}
+
+// The main menu button text showing the selected difficulty
+#[derive(Component)]
+struct DifficultyLabel;
+
+// Full screen dimmed node that holds one menu screen. Children are laid out top to bottom.
+pub fn spawn_menu_root(commands: &mut Commands) -> Entity {
+    commands
+        .spawn_bundle(NodeBundle {
+            style: Style {
+                size: Size::new(Val::Percent(100.0), Val::Percent(100.0)),
+                position_type: PositionType::Absolute,
+                justify_content: JustifyContent::Center,
+                align_items: AlignItems::Center,
+                flex_direction: FlexDirection::ColumnReverse,
+                ..Default::default()
+            },
+            color: BACKGROUND_COLOR.into(),
+            ..Default::default()
+        })
+        .insert(MenuScreen)
+        .id()
+}
+
+// Titles never change apart from the language, so they're given as a translation key
+pub fn spawn_menu_title(parent: &mut ChildBuilder, font: &Handle<Font>, locale: &Locale, key: &'static str) {
+    parent
+        .spawn_bundle(TextBundle {
+            style: Style {
+                margin: Rect {
+                    bottom: Px(32.0),
+                    ..Default::default()
+                },
+                ..Default::default()
+            },
+            text: Text::with_section(
+                locale.get(key),
+                TextStyle {
+                    font: font.clone(),
+                    font_size: 82.0,
+                    color: Color::WHITE,
+                },
+                Default::default(),
+            ),
+            ..Default::default()
+        })
+        .insert(LocalizedText::new(key));
+}
+
+pub fn spawn_menu_text(parent: &mut ChildBuilder, font: &Handle<Font>, value: &str) {
+    parent.spawn_bundle(TextBundle {
+        style: Style {
+            margin: Rect::all(Px(4.0)),
+            ..Default::default()
+        },
+        text: Text::with_section(
+            value,
+            TextStyle {
+                font: font.clone(),
+                font_size: 30.0,
+                color: Color::WHITE,
+            },
+            Default::default(),
+        ),
+        ..Default::default()
+    });
+}
+
+// Returns the entity of the button's text, for buttons whose label changes
+pub fn spawn_menu_button(parent: &mut ChildBuilder, font: &Handle<Font>, label: &str, index: usize, action: MenuAction, enabled: bool) -> Entity {
+    spawn_button(parent, font, label, None, index, action, enabled)
+}
+
+// A button with a fixed label, given as a translation key
+pub fn spawn_localized_button(parent: &mut ChildBuilder, font: &Handle<Font>, locale: &Locale, key: &'static str, index: usize, action: MenuAction, enabled: bool) -> Entity {
+    spawn_button(parent, font, locale.get(key), Some(key), index, action, enabled)
+}
+
+fn spawn_button(parent: &mut ChildBuilder, font: &Handle<Font>, label: &str, key: Option<&'static str>, index: usize, action: MenuAction, enabled: bool) -> Entity {
+    let mut text_entity = None;
+
+    parent
+        .spawn_bundle(ButtonBundle {
+            style: Style {
+                size: Size::new(Px(BUTTON_WIDTH), Px(BUTTON_HEIGHT)),
+                margin: Rect::all(Px(8.0)),
+                justify_content: JustifyContent::Center,
+                align_items: AlignItems::Center,
+                ..Default::default()
+            },
+            color: if enabled { BUTTON_COLOR.into() } else { DISABLED_BUTTON_COLOR.into() },
+            ..Default::default()
+        })
+        .insert(MenuButton {
+            index,
+            action,
+            enabled,
+        })
+        .with_children(|button| {
+            let mut text = button.spawn_bundle(TextBundle {
+                text: Text::with_section(
+                    label,
+                    TextStyle {
+                        font: font.clone(),
+                        font_size: 36.0,
+                        color: if enabled { Color::WHITE } else { DISABLED_TEXT_COLOR },
+                    },
+                    Default::default(),
+                ),
+                ..Default::default()
+            });
+            if let Some(key) = key {
+                text.insert(LocalizedText::new(key));
+            }
+            text_entity = Some(text.id());
+        });
+
+    text_entity.unwrap()
+}
+
+pub fn despawn_menu_screens(
+    mut commands: Commands,
+    screen_query: Query<Entity, With<MenuScreen>>,
+) {
+    for screen in screen_query.iter() {
+        commands.entity(screen).despawn_recursive();
+    }
+}
+
+// Only one menu screen exists at a time, so a new one appearing means the focus starts over
+fn reset_menu_focus(
+    mut focus: ResMut<MenuFocus>,
+    screen_query: Query<Entity, Added<MenuScreen>>,
+    button_query: Query<&MenuButton>,
+) {
+    if screen_query.iter().next().is_some() {
+        focus.0 = button_query
+            .iter()
+            .filter(|button| button.enabled)
+            .map(|button| button.index)
+            .min()
+            .unwrap_or(0);
+    }
+}
+
+fn gamepad_just_pressed(gamepad_input: &Input<GamepadButton>, button_type: GamepadButtonType) -> bool {
+    (0..MAX_GAMEPADS).map(Gamepad).any(|gamepad| gamepad_input.just_pressed(GamepadButton(gamepad, button_type)))
+}
+
+fn menu_navigation(
+    mut keyboard_input: ResMut<Input<KeyCode>>,
+    gamepad_input: Res<Input<GamepadButton>>,
+    mut focus: ResMut<MenuFocus>,
+    mut actions: EventWriter<MenuActionEvent>,
+    button_query: Query<&MenuButton>,
+    interaction_query: Query<(&Interaction, &MenuButton), Changed<Interaction>>,
+) {
+    let mut enabled: Vec<(usize, MenuAction)> = button_query
+        .iter()
+        .filter(|button| button.enabled)
+        .map(|button| (button.index, button.action))
+        .collect();
+    if enabled.is_empty() {
+        return;
+    }
+    enabled.sort_by_key(|(index, _)| *index);
+
+    for (interaction, button) in interaction_query.iter() {
+        if button.enabled {
+            match interaction {
+                Interaction::Hovered => focus.0 = button.index,
+                Interaction::Clicked => {
+                    focus.0 = button.index;
+                    actions.send(MenuActionEvent(button.action));
+                }
+                Interaction::None => {}
+            }
+        }
+    }
+
+    let position = enabled.iter().position(|(index, _)| *index == focus.0).unwrap_or(0);
+
+    if keyboard_input.just_pressed(KeyCode::Up) || keyboard_input.just_pressed(KeyCode::W) || gamepad_just_pressed(&gamepad_input, GamepadButtonType::DPadUp) {
+        focus.0 = enabled[(position + enabled.len() - 1) % enabled.len()].0;
+    }
+    if keyboard_input.just_pressed(KeyCode::Down) || keyboard_input.just_pressed(KeyCode::S) || gamepad_just_pressed(&gamepad_input, GamepadButtonType::DPadDown) {
+        focus.0 = enabled[(position + 1) % enabled.len()].0;
+    }
+
+    if keyboard_input.just_pressed(KeyCode::Return) || keyboard_input.just_pressed(KeyCode::Space) || gamepad_just_pressed(&gamepad_input, GamepadButtonType::South) {
+        if let Some((_, action)) = enabled.iter().find(|(index, _)| *index == focus.0) {
+            actions.send(MenuActionEvent(*action));
+        }
+        // Otherwise the same press would also reach the game (Space pauses it) on the frame the menu closes
+        keyboard_input.reset(KeyCode::Return);
+        keyboard_input.reset(KeyCode::Space);
+    }
+
+    if keyboard_input.just_pressed(KeyCode::Escape) || gamepad_just_pressed(&gamepad_input, GamepadButtonType::East) {
+        actions.send(MenuActionEvent(MenuAction::Back));
+        keyboard_input.reset(KeyCode::Escape);
+    }
+}
+
+fn menu_button_colors(
+    focus: Res<MenuFocus>,
+    mut button_query: Query<(&MenuButton, &mut UiColor)>,
+) {
+    for (button, mut color) in button_query.iter_mut() {
+        let wanted =
+            if !button.enabled {
+                DISABLED_BUTTON_COLOR
+            } else if button.index == focus.0 {
+                FOCUSED_BUTTON_COLOR
+            } else {
+                BUTTON_COLOR
+            };
+
+        if color.0 != wanted {
+            color.0 = wanted;
+        }
+    }
+}
+
+fn difficulty_label(locale: &Locale, difficulty: Difficulty) -> String {
+    locale.format("menu.difficulty", &[("difficulty", locale.get(difficulty.key()).to_string())])
+}
+
+fn update_difficulty_label(
+    difficulty: Res<Difficulty>,
+    locale: Res<Locale>,
+    mut label_query: Query<&mut Text, With<DifficultyLabel>>,
+) {
+    if difficulty.is_changed() || locale.is_changed() {
+        for mut text in label_query.iter_mut() {
+            text.sections[0].value = difficulty_label(&locale, *difficulty);
+        }
+    }
+}
+
+// The player and stars stay up behind the menu, frozen. The run's asteroids were cleared when InGame was left.
+fn enter_main_menu(
+    mut physics_time: ResMut<PhysicsTime>,
+    mut enemy_spawn_timer: ResMut<SpawnTimer>,
+    mut center_text: Query<&mut Text, (With<CenterMessageText>, Without<SubCenterText>)>,
+    mut sub_center_text: Query<&mut Text, (With<SubCenterText>, Without<CenterMessageText>)>,
+) {
+    physics_time.pause();
+    enemy_spawn_timer.0.pause();
+    center_text.single_mut().sections[0].value = String::from("");
+    sub_center_text.single_mut().sections[0].value = String::from("");
+}
+
+fn spawn_main_menu(
+    mut commands: Commands,
+    font: Res<BoldFont>,
+    locale: Res<Locale>,
+    difficulty: Res<Difficulty>,
+    loaded_ghost: Res<LoadedGhost>,
+) {
+    let root = spawn_menu_root(&mut commands);
+    let mut difficulty_text = None;
+    let has_saved_run = load_saved_run().is_some();
+    let has_ghost = loaded_ghost.0.is_some();
+
+    commands.entity(root).with_children(|parent| {
+        spawn_menu_title(parent, &font.0, &locale, "menu.title");
+        spawn_localized_button(parent, &font.0, &locale, "menu.continue", 0, MenuAction::Continue, has_saved_run);
+        spawn_localized_button(parent, &font.0, &locale, "menu.start", 1, MenuAction::Start, true);
+        spawn_localized_button(parent, &font.0, &locale, "menu.coop", 2, MenuAction::StartCoop, true);
+        spawn_localized_button(parent, &font.0, &locale, "menu.race_ghost", 3, MenuAction::RaceGhost, has_ghost);
+        difficulty_text = Some(spawn_menu_button(parent, &font.0, &difficulty_label(&locale, *difficulty), 4, MenuAction::CycleDifficulty, true));
+        spawn_localized_button(parent, &font.0, &locale, "menu.options", 5, MenuAction::Options, true);
+        spawn_localized_button(parent, &font.0, &locale, "menu.high_scores", 6, MenuAction::HighScores, true);
+        spawn_localized_button(parent, &font.0, &locale, "menu.quit", 7, MenuAction::Quit, true);
+    });
+
+    if let Some(difficulty_text) = difficulty_text {
+        commands.entity(difficulty_text).insert(DifficultyLabel);
+    }
+}
+
+fn main_menu_actions(
+    mut actions: EventReader<MenuActionEvent>,
+    mut state: ResMut<State<AppState>>,
+    mut difficulty: ResMut<Difficulty>,
+    mut player_count: ResMut<PlayerCount>,
+    mut exit: EventWriter<AppExit>,
+) {
+    for MenuActionEvent(action) in actions.iter() {
+        match action {
+            MenuAction::Start => {
+                player_count.0 = 1;
+                let _ = state.set(AppState::InGame);
+            }
+            MenuAction::StartCoop => {
+                player_count.0 = 2;
+                let _ = state.set(AppState::InGame);
+            }
+            MenuAction::CycleDifficulty => *difficulty = difficulty.next(),
+            MenuAction::Options => {
+                let _ = state.push(AppState::Options);
+            }
+            MenuAction::HighScores => {
+                let _ = state.push(AppState::HighScores);
+            }
+            MenuAction::Quit => exit.send(AppExit),
+            _ => {}
+        }
+    }
+}
+
+fn spawn_high_scores(
+    mut commands: Commands,
+    font: Res<BoldFont>,
+    locale: Res<Locale>,
+    high_scores: Res<HighScores>,
+) {
+    let root = spawn_menu_root(&mut commands);
+
+    commands.entity(root).with_children(|parent| {
+        spawn_menu_title(parent, &font.0, &locale, "high_scores.title");
+
+        if high_scores.0.is_empty() {
+            spawn_menu_text(parent, &font.0, locale.get("high_scores.empty"));
+        }
+        for (place, entry) in high_scores.0.iter().enumerate() {
+            spawn_menu_text(
+                parent,
+                &font.0,
+                &format!("{}.  {}  -  {:.1}s  ({})", place + 1, entry.score, entry.survival_time, locale.get(entry.difficulty.key())),
+            );
+        }
+
+        spawn_localized_button(parent, &font.0, &locale, "menu.back", 0, MenuAction::Back, true);
+    });
+}
+
+fn high_score_actions(
+    mut actions: EventReader<MenuActionEvent>,
+    mut state: ResMut<State<AppState>>,
+) {
+    for MenuActionEvent(action) in actions.iter() {
+        if *action == MenuAction::Back {
+            let _ = state.pop();
+        }
+    }
+}
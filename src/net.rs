@@ -0,0 +1,558 @@
+use std::collections::BTreeMap;
+use std::io::ErrorKind;
+use std::net::{SocketAddr, UdpSocket};
+use std::time::Duration;
+
+use bevy::prelude::*;
+use bevy::ui::Val::Px;
+use heron::prelude::*;
+use heron::PhysicsSteps;
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+
+use crate::pause::set_paused;
+use crate::player::input_direction;
+use crate::types::*;
+
+// Online play is delay based lockstep. Only inputs go over the network: each side sends its input for a tick
+// INPUT_DELAY ticks ahead of when it's used, and the game only steps once it has both players' inputs for the next tick.
+// Both sides start from the same seed, so the runs stay the same as long as the game itself is deterministic,
+// which means both instances have to be the same build on the same kind of machine.
+const TICK: Duration = Duration::from_nanos(16_666_667);
+const INPUT_DELAY: u32 = 3;
+// Inputs the other side hasn't confirmed yet go out again with every packet, so a lost packet is covered by the next one
+const MAX_INPUTS_PER_PACKET: usize = 32;
+// Real time the game is allowed to fall behind before it gives up on catching up
+const MAX_TICK_BACKLOG: u32 = 4;
+
+// Bump this whenever the packets or the simulation change, so mismatched builds don't try to play together
+const PROTOCOL_VERSION: u32 = 1;
+const MAX_PACKET_SIZE: usize = 2048;
+const HELLO_INTERVAL: f64 = 0.5;
+const CONNECTION_TIMEOUT: f64 = 5.0;
+// Short stalls are normal with any latency, so the waiting message only shows for longer ones
+const WAITING_MESSAGE_DELAY: f32 = 0.25;
+
+// Added by EarthEscapePlugin when it's given a NetConfig
+pub struct NetPlugin(pub NetConfig);
+
+impl Plugin for NetPlugin {
+    fn build(&self, app: &mut App) {
+        let socket = match UdpSocket::bind(("0.0.0.0", self.0.port)).and_then(|socket| socket.set_nonblocking(true).map(|_| socket)) {
+            Ok(socket) => socket,
+            Err(error) => {
+                error!("Couldn't open UDP port {} for online play: {}", self.0.port, error);
+                return;
+            }
+        };
+
+        app
+            .insert_resource(NetSession::new(self.0.clone(), socket))
+            // Physics has to step by exactly one tick each time the game moves, instead of by the frame time
+            .insert_resource(PhysicsSteps::every_frame(TICK))
+            // After the startup systems, once BoldFont is in
+            .add_startup_system_to_stage(StartupStage::PostStartup, setup)
+            .add_system_to_stage(CoreStage::PreUpdate, receive_packets.label("receive_packets"))
+            .add_system_to_stage(CoreStage::PreUpdate, step_lockstep.after("receive_packets"))
+            .add_system(show_status)
+            .add_system_set(
+                SystemSet::on_update(AppState::InGame)
+                    .with_system(hold_physics.after("start_run").after("pause_menu_actions"))
+                    .with_system(leave_lost_session)
+            )
+            .add_system_set(SystemSet::on_exit(AppState::InGame).with_system(end_session));
+    }
+}
+
+// Text showing the state of the online session, like waiting for the other player
+#[derive(Component)]
+struct NetStatusText;
+
+#[derive(PartialEq)]
+enum Phase {
+    Connecting,
+    Playing,
+    // Left the game. Playing online again takes a restart, so the menu's Start goes back to playing offline.
+    Ended,
+}
+
+// Directions are sent as whole numbers so both sides end up with exactly the same floats
+#[derive(Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+struct TickInput {
+    x: i8,
+    y: i8,
+    restart: bool,
+}
+
+impl TickInput {
+    fn new(direction: Vec2, restart: bool) -> Self {
+        TickInput {
+            x: (direction.x * 127.).round() as i8,
+            y: (direction.y * 127.).round() as i8,
+            restart,
+        }
+    }
+
+    fn direction(self) -> Vec2 {
+        Vec2::new(self.x as f32 / 127., self.y as f32 / 127.)
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+enum Packet {
+    Hello { version: u32 },
+    // The host picks the run setup, so both sides start from the same place
+    Welcome { seed: u64, difficulty: Difficulty },
+    // `ack` is the first tick of the receiver's inputs that the sender is still waiting on
+    Inputs { ack: u32, first_tick: u32, inputs: Vec<TickInput> },
+}
+
+struct NetSession {
+    config: NetConfig,
+    socket: UdpSocket,
+    peer: Option<SocketAddr>,
+    phase: Phase,
+    // The host is player one, whoever joins is player two
+    local_id: usize,
+    welcome: Option<(u64, Difficulty)>,
+    // The next tick the game will step to
+    tick: u32,
+    since_tick: Duration,
+    stalled_for: f32,
+    local_inputs: BTreeMap<u32, TickInput>,
+    remote_inputs: BTreeMap<u32, TickInput>,
+    remote_ack: u32,
+    restart_requested: bool,
+    // Put back once the online game is over
+    offline_seed: Option<u64>,
+    last_heard: f64,
+    last_hello: f64,
+    // Set when the other player stopped answering, so the menu can say why the game ended
+    connection_lost: bool,
+    // Outgoing packets held back to fake latency, with the time they're due to go out
+    delayed: Vec<(f64, Vec<u8>)>,
+}
+
+impl NetSession {
+    fn new(config: NetConfig, socket: UdpSocket) -> Self {
+        let (peer, local_id) = match config.role {
+            NetRole::Host => (None, 0),
+            NetRole::Join(address) => (Some(address), 1),
+        };
+
+        NetSession {
+            config,
+            socket,
+            peer,
+            phase: Phase::Connecting,
+            local_id,
+            welcome: None,
+            tick: 0,
+            since_tick: Duration::ZERO,
+            stalled_for: 0.0,
+            local_inputs: BTreeMap::new(),
+            remote_inputs: BTreeMap::new(),
+            remote_ack: 0,
+            restart_requested: false,
+            offline_seed: None,
+            last_heard: 0.0,
+            last_hello: f64::MIN,
+            connection_lost: false,
+            delayed: Vec::new(),
+        }
+    }
+
+    fn send(&mut self, packet: &Packet, now: f64) {
+        let peer = match self.peer {
+            Some(peer) => peer,
+            None => return,
+        };
+
+        if self.config.packet_loss > 0.0 && rand::thread_rng().gen::<f32>() < self.config.packet_loss {
+            return;
+        }
+
+        let bytes = match ron::to_string(packet) {
+            Ok(text) => text.into_bytes(),
+            Err(error) => {
+                warn!("Couldn't encode a packet: {}", error);
+                return;
+            }
+        };
+
+        if self.config.latency_ms > 0 {
+            self.delayed.push((now + self.config.latency_ms as f64 / 1000., bytes));
+        } else {
+            let _ = self.socket.send_to(&bytes, peer);
+        }
+    }
+
+    fn flush_delayed(&mut self, now: f64) {
+        if let Some(peer) = self.peer {
+            let socket = &self.socket;
+            self.delayed.retain(|(due, bytes)| {
+                if *due <= now {
+                    let _ = socket.send_to(bytes, peer);
+                    false
+                } else {
+                    true
+                }
+            });
+        }
+    }
+
+    fn start(&mut self, now: f64) {
+        self.phase = Phase::Playing;
+        self.tick = 0;
+        self.since_tick = Duration::ZERO;
+        self.stalled_for = 0.0;
+        self.remote_inputs.clear();
+        self.remote_ack = 0;
+        self.last_heard = now;
+        // Nobody has pressed anything yet for the first few ticks
+        self.local_inputs = (0..INPUT_DELAY).map(|tick| (tick, TickInput::default())).collect();
+    }
+
+    // The first tick the other side still has to send
+    fn ack(&self) -> u32 {
+        let mut ack = self.tick;
+        while self.remote_inputs.contains_key(&ack) {
+            ack += 1;
+        }
+        ack
+    }
+
+    fn send_inputs(&mut self, now: f64) {
+        let first_tick = self.remote_ack;
+        let inputs: Vec<TickInput> = self.local_inputs
+            .range(first_tick..)
+            .take(MAX_INPUTS_PER_PACKET)
+            .map(|(_, input)| *input)
+            .collect();
+        let packet = Packet::Inputs {
+            ack: self.ack(),
+            first_tick,
+            inputs,
+        };
+        self.send(&packet, now);
+    }
+
+    fn timed_out(&self, now: f64) -> bool {
+        self.phase == Phase::Playing && now - self.last_heard > CONNECTION_TIMEOUT
+    }
+}
+
+fn setup(
+    mut commands: Commands,
+    font: Res<BoldFont>,
+) {
+    commands
+        .spawn_bundle(NodeBundle {
+            style: Style {
+                size: Size::new(Val::Percent(100.0), Val::Percent(100.0)),
+                position_type: PositionType::Absolute,
+                justify_content: JustifyContent::Center,
+                align_items: AlignItems::FlexEnd,
+                // Just under the hearts
+                padding: Rect {
+                    top: Px(88.0),
+                    ..Default::default()
+                },
+                ..Default::default()
+            },
+            color: Color::NONE.into(),
+            ..Default::default()
+        })
+        .with_children(|parent| {
+            parent
+                .spawn_bundle(TextBundle {
+                    text: Text::with_section(
+                        "",
+                        TextStyle {
+                            font: font.0.clone(),
+                            font_size: 32.0,
+                            color: Color::WHITE,
+                        },
+                        Default::default(),
+                    ),
+                    ..Default::default()
+                })
+                .insert(NetStatusText);
+        });
+}
+
+// Handles the handshake, and stores the other player's inputs as they come in
+fn receive_packets(
+    time: Res<Time>,
+    mut session: ResMut<NetSession>,
+    mut state: ResMut<State<AppState>>,
+    mut seed_override: ResMut<SeedOverride>,
+    mut difficulty: ResMut<Difficulty>,
+    mut player_count: ResMut<PlayerCount>,
+    mut sim: ResMut<SimStep>,
+) {
+    let now = time.seconds_since_startup();
+    let in_menu = *state.current() == AppState::MainMenu;
+    let mut buffer = [0; MAX_PACKET_SIZE];
+
+    loop {
+        let (length, from) = match session.socket.recv_from(&mut buffer) {
+            Ok(received) => received,
+            // Windows reports the other side not listening (yet) as an error on the next receive
+            Err(error) if error.kind() == ErrorKind::ConnectionReset => continue,
+            Err(_) => break,
+        };
+        let packet: Packet = match std::str::from_utf8(&buffer[..length]).ok().and_then(|text| ron::from_str(text).ok()) {
+            Some(packet) => packet,
+            None => continue,
+        };
+
+        match packet {
+            Packet::Hello { version } => {
+                if session.config.role != NetRole::Host || session.phase == Phase::Ended {
+                    continue;
+                }
+                if version != PROTOCOL_VERSION {
+                    warn!("Ignoring a player on protocol version {}, expected {}", version, PROTOCOL_VERSION);
+                    continue;
+                }
+
+                if session.phase == Phase::Connecting && in_menu {
+                    let seed = seed_override.0.unwrap_or_else(rand::random);
+                    session.peer = Some(from);
+                    session.welcome = Some((seed, *difficulty));
+                    start_online_run(&mut session, seed, now, &mut seed_override, &mut player_count, &mut sim, &mut state);
+                    info!("{} joined", from);
+                }
+
+                // Sent again for every hello, in case the first welcome got lost
+                if session.peer == Some(from) {
+                    if let Some((seed, difficulty)) = session.welcome {
+                        session.send(&Packet::Welcome { seed, difficulty }, now);
+                    }
+                }
+            }
+            Packet::Welcome { seed, difficulty: host_difficulty } => {
+                if session.phase == Phase::Connecting && session.peer == Some(from) && in_menu {
+                    *difficulty = host_difficulty;
+                    start_online_run(&mut session, seed, now, &mut seed_override, &mut player_count, &mut sim, &mut state);
+                    info!("Joined {}", from);
+                }
+            }
+            Packet::Inputs { ack, first_tick, inputs } => {
+                if session.phase != Phase::Playing || session.peer != Some(from) {
+                    continue;
+                }
+                session.last_heard = now;
+                session.remote_ack = session.remote_ack.max(ack);
+
+                let current_tick = session.tick;
+                for (tick, input) in (first_tick..).zip(inputs) {
+                    if tick >= current_tick {
+                        session.remote_inputs.entry(tick).or_insert(input);
+                    }
+                }
+
+                // Anything the other side has confirmed doesn't need sending again, once this side has used it too
+                let keep_from = session.remote_ack.min(session.tick);
+                let kept = session.local_inputs.split_off(&keep_from);
+                session.local_inputs = kept;
+            }
+        }
+    }
+
+    // Joining keeps saying hello until the host answers
+    if session.phase == Phase::Connecting && session.config.role != NetRole::Host && in_menu && now - session.last_hello >= HELLO_INTERVAL {
+        session.last_hello = now;
+        session.send(&Packet::Hello { version: PROTOCOL_VERSION }, now);
+    }
+}
+
+fn start_online_run(
+    session: &mut NetSession,
+    seed: u64,
+    now: f64,
+    seed_override: &mut SeedOverride,
+    player_count: &mut PlayerCount,
+    sim: &mut SimStep,
+    state: &mut State<AppState>,
+) {
+    session.start(now);
+    session.offline_seed = seed_override.0;
+    seed_override.0 = Some(seed);
+    player_count.0 = 2;
+    sim.lockstep = true;
+    sim.delta = Duration::ZERO;
+    let _ = state.set(AppState::InGame);
+}
+
+// Steps the game by one tick once both players' inputs for it are in
+fn step_lockstep(
+    time: Res<Time>,
+    mut session: ResMut<NetSession>,
+    state: Res<State<AppState>>,
+    keyboard_input: Res<Input<KeyCode>>,
+    gamepad_buttons: Res<Input<GamepadButton>>,
+    gamepad_axes: Res<Axis<GamepadAxis>>,
+    controls: Res<ControlScheme>,
+    mut sim: ResMut<SimStep>,
+    mut game_paused: ResMut<GamePaused>,
+    mut physics_time: ResMut<PhysicsTime>,
+    mut enemy_spawn_timer: ResMut<SpawnTimer>,
+    mut restart_requests: EventReader<RestartRequestEvent>,
+    mut start_run_events: EventWriter<StartRunEvent>,
+    mut player_query: Query<(&PlayerId, &mut PlayerInput), With<Player>>,
+) {
+    if session.phase != Phase::Playing {
+        return;
+    }
+
+    let now = time.seconds_since_startup();
+    sim.delta = Duration::ZERO;
+
+    if restart_requests.iter().count() > 0 {
+        session.restart_requested = true;
+    }
+    // A restart from the pause menu has to go out with a tick, and ticks don't happen while paused
+    if session.restart_requested && game_paused.0 {
+        set_paused(false, &mut physics_time, &mut enemy_spawn_timer, &mut game_paused);
+    }
+
+    // In the options, a replay or the pause menu the game stands still on both ends
+    if *state.current() == AppState::InGame && !game_paused.0 {
+        session.since_tick = (session.since_tick + time.delta()).min(TICK * MAX_TICK_BACKLOG);
+
+        let sample_tick = session.tick + INPUT_DELAY;
+        if !session.local_inputs.contains_key(&sample_tick) {
+            let direction = input_direction(&keyboard_input, &gamepad_buttons, &gamepad_axes, *controls, 0);
+            let restart = std::mem::take(&mut session.restart_requested);
+            session.local_inputs.insert(sample_tick, TickInput::new(direction, restart));
+        }
+
+        let tick = session.tick;
+        let local = session.local_inputs.get(&tick).copied();
+        let remote = session.remote_inputs.get(&tick).copied();
+
+        match (local, remote) {
+            (Some(local), Some(remote)) if session.since_tick >= TICK => {
+                session.since_tick -= TICK;
+                session.tick += 1;
+                session.remote_inputs.remove(&tick);
+                session.stalled_for = 0.0;
+                sim.delta = TICK;
+
+                let mut inputs = [local, remote];
+                if session.local_id == 1 {
+                    inputs.swap(0, 1);
+                }
+                for (id, mut input) in player_query.iter_mut() {
+                    if let Some(tick_input) = inputs.get(id.0) {
+                        input.network = Some(tick_input.direction());
+                    }
+                }
+
+                if inputs.iter().any(|input| input.restart) {
+                    start_run_events.send(StartRunEvent);
+                }
+            }
+            (_, None) => session.stalled_for += time.delta_seconds(),
+            _ => {}
+        }
+    }
+
+    // Sent every frame even when nothing's new, which doubles as a keep alive
+    session.send_inputs(now);
+    session.flush_delayed(now);
+}
+
+// Physics only steps on frames where the game moves. Runs after anything else that might have resumed it.
+fn hold_physics(
+    sim: Res<SimStep>,
+    game_paused: Res<GamePaused>,
+    mut physics_time: ResMut<PhysicsTime>,
+) {
+    if !sim.lockstep || game_paused.0 {
+        return;
+    }
+
+    if sim.is_stalled() {
+        physics_time.pause();
+    } else {
+        physics_time.resume();
+    }
+}
+
+fn end_session(
+    mut session: ResMut<NetSession>,
+    mut sim: ResMut<SimStep>,
+    mut seed_override: ResMut<SeedOverride>,
+) {
+    if session.phase == Phase::Playing {
+        session.phase = Phase::Ended;
+        sim.lockstep = false;
+        seed_override.0 = session.offline_seed;
+    }
+}
+
+// Without the other player's inputs the game can't move again, so it goes back to the menu (which ends the session)
+fn leave_lost_session(
+    time: Res<Time>,
+    mut session: ResMut<NetSession>,
+    mut state: ResMut<State<AppState>>,
+) {
+    if session.timed_out(time.seconds_since_startup()) && !session.connection_lost {
+        session.connection_lost = true;
+        let _ = state.set(AppState::MainMenu);
+    }
+}
+
+fn show_status(
+    locale: Res<Locale>,
+    session: Res<NetSession>,
+    state: Res<State<AppState>>,
+    game_paused: Res<GamePaused>,
+    mut text_query: Query<&mut Text, With<NetStatusText>>,
+) {
+    let status = match session.phase {
+        Phase::Connecting if *state.current() == AppState::MainMenu => match session.config.role {
+            NetRole::Host => locale.get("net.hosting").replace("{port}", &session.config.port.to_string()),
+            NetRole::Join(address) => locale.get("net.joining").replace("{address}", &address.to_string()),
+        },
+        Phase::Ended if session.connection_lost && *state.current() == AppState::MainMenu => locale.get("net.connection_lost").to_string(),
+        Phase::Playing if !game_paused.0 && session.stalled_for > WAITING_MESSAGE_DELAY => locale.get("net.waiting").to_string(),
+        _ => String::new(),
+    };
+
+    for mut text in text_query.iter_mut() {
+        if text.sections[0].value != status {
+            text.sections[0].value = status.clone();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn tick_input_keeps_full_directions_exact() {
+        for direction in [Vec2::ZERO, Vec2::X, -Vec2::X, Vec2::Y, Vec2::new(1.0, -1.0)] {
+            assert_eq!(TickInput::new(direction, false).direction(), direction);
+        }
+        assert!(TickInput::new(Vec2::ZERO, false) == TickInput::default());
+    }
+
+    #[test]
+    fn tick_input_is_close_to_the_original_direction() {
+        let direction = Vec2::new(1.0, 1.0).normalize();
+        let quantized = TickInput::new(direction, true).direction();
+        assert!((quantized - direction).abs().max_element() <= 0.5 / 127.);
+    }
+
+    #[test]
+    fn tick_input_quantizes_to_the_same_input_twice() {
+        // Both players step with the quantized direction, so sending it again has to give back exactly the same thing
+        let input = TickInput::new(Vec2::new(0.3, -0.77), false);
+        assert!(TickInput::new(input.direction(), false) == input);
+    }
+}
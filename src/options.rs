@@ -0,0 +1,301 @@
+use bevy::prelude::*;
+use bevy::window::WindowMode;
+
+use crate::menu::*;
+use crate::persistence;
+use crate::types::*;
+
+pub const SETTINGS_FILE: &str = "settings.ron";
+
+const WINDOW_SIZES: [(f32, f32); 5] = [
+    (960., 640.),
+    (1200., 800.),
+    (1440., 960.),
+    (1600., 900.),
+    (1920., 1080.),
+];
+const VOLUME_STEP: f32 = 0.1;
+const SHAKE_STEPS: [f32; 3] = [1.0, 0.5, 0.0];
+
+// Order the settings appear in on the options screen
+const SETTING_ORDER: [SettingKind; 9] = [
+    SettingKind::Fullscreen,
+    SettingKind::WindowSize,
+    SettingKind::MasterVolume,
+    SettingKind::MusicVolume,
+    SettingKind::SfxVolume,
+    SettingKind::CameraShake,
+    SettingKind::CameraMotion,
+    SettingKind::Controls,
+    SettingKind::Language,
+];
+
+// Translation keys for the camera motion levels, from the most motion to none
+const CAMERA_MOTION_KEYS: [&str; 3] = ["options.camera_motion.full", "options.camera_motion.reduced", "options.camera_motion.locked"];
+
+pub struct OptionsPlugin;
+
+impl Plugin for OptionsPlugin {
+    fn build(&self, app: &mut App) {
+        app
+            .add_system(apply_settings)
+            .add_system(save_settings)
+            .add_system(update_setting_labels)
+            .add_system_set(SystemSet::on_enter(AppState::Options).with_system(spawn_options))
+            .add_system_set(
+                SystemSet::on_update(AppState::Options)
+                    .with_system(options_actions.after("menu_navigation"))
+                    .with_system(adjust_focused_setting)
+            )
+            .add_system_set(SystemSet::on_exit(AppState::Options).with_system(despawn_menu_screens));
+    }
+}
+
+// Text on an options button, rewritten whenever the setting changes
+#[derive(Component)]
+struct SettingLabel(SettingKind);
+
+// Reads the settings file, falling back to the defaults. Used by EarthEscapePlugin before the window is created.
+pub fn load_settings() -> Settings {
+    persistence::load(SETTINGS_FILE).unwrap_or_default()
+}
+
+fn percent(value: f32) -> String {
+    format!("{}%", (value * 100.).round() as u32)
+}
+
+// 0 is full motion, 2 is a camera locked to the player
+fn camera_motion_level(camera: &CameraSettings) -> usize {
+    if camera.look_ahead && camera.dynamic_zoom {
+        0
+    } else if camera.smooth_follow {
+        1
+    } else {
+        2
+    }
+}
+
+fn setting_label(locale: &Locale, settings: &Settings, kind: SettingKind) -> String {
+    let on_off = |on: bool| locale.get(if on { "options.on" } else { "options.off" }).to_string();
+
+    let (key, value) = match kind {
+        SettingKind::Fullscreen => ("options.fullscreen", on_off(settings.fullscreen)),
+        SettingKind::WindowSize => ("options.window_size", format!("{}x{}", settings.window_width, settings.window_height)),
+        SettingKind::MasterVolume => ("options.master_volume", percent(settings.volumes.master)),
+        SettingKind::MusicVolume => ("options.music_volume", percent(settings.volumes.music)),
+        SettingKind::SfxVolume => ("options.sfx_volume", percent(settings.volumes.sfx)),
+        SettingKind::CameraShake => {
+            if settings.camera.shake_scale <= 0.0 {
+                ("options.camera_shake", on_off(false))
+            } else {
+                ("options.camera_shake", percent(settings.camera.shake_scale))
+            }
+        }
+        SettingKind::CameraMotion => ("options.camera_motion", locale.get(CAMERA_MOTION_KEYS[camera_motion_level(&settings.camera)]).to_string()),
+        SettingKind::Controls => ("options.controls", locale.get(settings.controls.key()).to_string()),
+        // Language names are translated too, since not every font can show every language's own name for itself
+        SettingKind::Language => ("options.language", locale.get(&format!("language.{}", settings.language.code())).to_string()),
+    };
+
+    locale.format(key, &[("value", value)])
+}
+
+fn step_volume(volume: f32, direction: i32) -> f32 {
+    let stepped = ((volume / VOLUME_STEP).round() as i32 + direction) as f32 * VOLUME_STEP;
+    // Stepping past either end wraps around, so the activate button alone can reach every value
+    if stepped > 1.0 + f32::EPSILON {
+        0.0
+    } else if stepped < -f32::EPSILON {
+        1.0
+    } else {
+        stepped
+    }
+}
+
+// Index of `current` in `values` moved by `direction`, wrapping at both ends
+fn step_index(current: usize, len: usize, direction: i32) -> usize {
+    ((current as i32 + direction).rem_euclid(len as i32)) as usize
+}
+
+fn change_setting(settings: &mut Settings, kind: SettingKind, direction: i32) {
+    match kind {
+        SettingKind::Fullscreen => settings.fullscreen = !settings.fullscreen,
+        SettingKind::WindowSize => {
+            let current = WINDOW_SIZES
+                .iter()
+                .position(|(width, height)| *width == settings.window_width && *height == settings.window_height)
+                .unwrap_or(1);
+            let (width, height) = WINDOW_SIZES[step_index(current, WINDOW_SIZES.len(), direction)];
+            settings.window_width = width;
+            settings.window_height = height;
+        }
+        SettingKind::MasterVolume => settings.volumes.master = step_volume(settings.volumes.master, direction),
+        SettingKind::MusicVolume => settings.volumes.music = step_volume(settings.volumes.music, direction),
+        SettingKind::SfxVolume => settings.volumes.sfx = step_volume(settings.volumes.sfx, direction),
+        SettingKind::CameraShake => {
+            let current = SHAKE_STEPS
+                .iter()
+                .position(|step| (*step - settings.camera.shake_scale).abs() < 0.01)
+                .unwrap_or(0);
+            settings.camera.shake_scale = SHAKE_STEPS[step_index(current, SHAKE_STEPS.len(), direction)];
+        }
+        SettingKind::CameraMotion => {
+            let camera = &mut settings.camera;
+            let next = step_index(camera_motion_level(camera), CAMERA_MOTION_KEYS.len(), direction);
+            camera.smooth_follow = next < 2;
+            camera.look_ahead = next == 0;
+            camera.dynamic_zoom = next == 0;
+        }
+        SettingKind::Controls => {
+            settings.controls =
+                if direction >= 0 {
+                    settings.controls.next()
+                } else {
+                    settings.controls.next().next()
+                };
+        }
+        SettingKind::Language => {
+            settings.language =
+                if direction >= 0 {
+                    settings.language.next()
+                } else {
+                    settings.language.next().next().next()
+                };
+        }
+    }
+}
+
+// Pushes the settings out to the resources and window that actually use them
+fn apply_settings(
+    settings: Res<Settings>,
+    mut windows: ResMut<Windows>,
+    mut volumes: ResMut<AudioVolumes>,
+    mut camera_settings: ResMut<CameraSettings>,
+    mut controls: ResMut<ControlScheme>,
+) {
+    if !settings.is_changed() {
+        return;
+    }
+
+    let window = windows.get_primary_mut().unwrap();
+    let mode = if settings.fullscreen { WindowMode::BorderlessFullscreen } else { WindowMode::Windowed };
+    if window.mode() != mode {
+        window.set_mode(mode);
+    }
+    if !settings.fullscreen && (window.requested_width() != settings.window_width || window.requested_height() != settings.window_height) {
+        window.set_resolution(settings.window_width, settings.window_height);
+    }
+
+    *volumes = settings.volumes;
+    *camera_settings = settings.camera;
+    *controls = settings.controls;
+}
+
+fn save_settings(
+    settings: Res<Settings>,
+) {
+    if settings.is_changed() && !settings.is_added() {
+        persistence::save(SETTINGS_FILE, &*settings);
+    }
+}
+
+fn update_setting_labels(
+    settings: Res<Settings>,
+    locale: Res<Locale>,
+    mut label_query: Query<(&mut Text, &SettingLabel)>,
+) {
+    if settings.is_changed() || locale.is_changed() {
+        for (mut text, SettingLabel(kind)) in label_query.iter_mut() {
+            text.sections[0].value = setting_label(&locale, &settings, *kind);
+        }
+    }
+}
+
+fn spawn_options(
+    mut commands: Commands,
+    font: Res<BoldFont>,
+    locale: Res<Locale>,
+    settings: Res<Settings>,
+) {
+    let root = spawn_menu_root(&mut commands);
+    let mut labels = Vec::new();
+
+    commands.entity(root).with_children(|parent| {
+        spawn_menu_title(parent, &font.0, &locale, "options.title");
+
+        for (index, kind) in SETTING_ORDER.iter().enumerate() {
+            let text = spawn_menu_button(parent, &font.0, &setting_label(&locale, &settings, *kind), index, MenuAction::ChangeSetting(*kind), true);
+            labels.push((text, *kind));
+        }
+
+        spawn_localized_button(parent, &font.0, &locale, "menu.back", SETTING_ORDER.len(), MenuAction::Back, true);
+    });
+
+    for (text, kind) in labels {
+        commands.entity(text).insert(SettingLabel(kind));
+    }
+}
+
+fn options_actions(
+    mut actions: EventReader<MenuActionEvent>,
+    mut state: ResMut<State<AppState>>,
+    mut settings: ResMut<Settings>,
+) {
+    for MenuActionEvent(action) in actions.iter() {
+        match action {
+            MenuAction::ChangeSetting(kind) => change_setting(&mut settings, *kind, 1),
+            MenuAction::Back => {
+                let _ = state.pop();
+            }
+            _ => {}
+        }
+    }
+}
+
+// Left and right step the focused setting down and up
+fn adjust_focused_setting(
+    keyboard_input: Res<Input<KeyCode>>,
+    focus: Res<MenuFocus>,
+    mut settings: ResMut<Settings>,
+) {
+    let direction =
+        if keyboard_input.just_pressed(KeyCode::Left) || keyboard_input.just_pressed(KeyCode::A) {
+            -1
+        } else if keyboard_input.just_pressed(KeyCode::Right) || keyboard_input.just_pressed(KeyCode::D) {
+            1
+        } else {
+            return;
+        };
+
+    if let Some(kind) = SETTING_ORDER.get(focus.0) {
+        change_setting(&mut settings, *kind, direction);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_volume(actual: f32, expected: f32) {
+        assert!((actual - expected).abs() < 1e-4, "volume was {}, expected {}", actual, expected);
+    }
+
+    #[test]
+    fn step_volume_moves_one_step() {
+        assert_volume(step_volume(0.5, 1), 0.6);
+        assert_volume(step_volume(0.5, -1), 0.4);
+        assert_volume(step_volume(0.9, 1), 1.0);
+    }
+
+    #[test]
+    fn step_volume_wraps_at_both_ends() {
+        assert_volume(step_volume(1.0, 1), 0.0);
+        assert_volume(step_volume(0.0, -1), 1.0);
+    }
+
+    #[test]
+    fn step_volume_snaps_to_the_grid() {
+        assert_volume(step_volume(0.43, 1), 0.5);
+        assert_volume(step_volume(0.43, -1), 0.3);
+    }
+}
@@ -0,0 +1,385 @@
+use bevy::prelude::*;
+use heron::prelude::*;
+use rand::Rng;
+
+use crate::utilities::*;
+use crate::types::*;
+
+// Upper limit on live particles across every emitter
+const PARTICLE_BUDGET: u32 = 1500;
+
+const THRUST_PARTICLES_PER_SECOND: f32 = 90.0;
+const PLAYER_HIT_DEBRIS: u32 = 16;
+const ASTEROID_HIT_DEBRIS: u32 = 4;
+const DEATH_EXPLOSION_PARTICLES: u32 = 220;
+
+pub struct ParticlePlugin;
+
+impl Plugin for ParticlePlugin {
+    fn build(&self, app: &mut App) {
+        app
+            .insert_resource(ParticleBudget::new(PARTICLE_BUDGET))
+            .add_event::<ParticleBurstEvent>()
+            .add_startup_system(setup)
+            .add_system(emit_collision_debris)
+            .add_system(emit_death_explosion)
+            .add_system(attach_thruster_emitter)
+            .add_system(emit_thrust.after("player_movement"))
+            .add_system(spawn_bursts)
+            .add_system(update_particles);
+    }
+}
+
+// The particle presets. Their look is set up in burst_spec and emit_thrust.
+#[derive(Clone, Copy, PartialEq)]
+enum ParticleEffect {
+    Thrust,
+    Debris,
+    Explosion,
+}
+
+// Owns a pool of finished particles so new ones can reuse their entities
+#[derive(Component)]
+struct ParticleEmitter {
+    effect: ParticleEffect,
+    pool: Vec<Entity>,
+    // Fractional particles carried over between frames for continuous emitters
+    spawn_accumulator: f32,
+}
+
+impl ParticleEmitter {
+    fn new(effect: ParticleEffect) -> Self {
+        ParticleEmitter {
+            effect,
+            pool: Vec::new(),
+            spawn_accumulator: 0.0,
+        }
+    }
+}
+
+// A single particle, moved and faded by the particle plugin
+#[derive(Component)]
+struct Particle {
+    // The emitter whose pool this particle goes back to when it dies
+    emitter: Entity,
+    velocity: Vec2,
+    age: f32,
+    lifetime: f32,
+    start_size: f32,
+    end_size: f32,
+    start_color: Color,
+    end_color: Color,
+}
+
+// Spawns a one-off burst of particles, like debris from a hit
+struct ParticleBurstEvent {
+    effect: ParticleEffect,
+    position: Vec3,
+    count: u32,
+}
+
+// Caps the number of live particles across every emitter
+struct ParticleBudget {
+    max: u32,
+    live: u32,
+}
+
+impl ParticleBudget {
+    fn new(max: u32) -> Self {
+        ParticleBudget {
+            max,
+            live: 0,
+        }
+    }
+
+    fn is_full(&self) -> bool {
+        self.live >= self.max
+    }
+}
+
+// What a single particle looks like and how it moves, decided when it is emitted
+struct ParticleSpec {
+    position: Vec3,
+    velocity: Vec2,
+    lifetime: f32,
+    start_size: f32,
+    end_size: f32,
+    start_color: Color,
+    end_color: Color,
+}
+
+fn setup(
+    mut commands: Commands,
+) {
+    // The burst effects aren't attached to anything, so they get emitter entities of their own to hold their pools
+    commands.spawn().insert(ParticleEmitter::new(ParticleEffect::Debris));
+    commands.spawn().insert(ParticleEmitter::new(ParticleEffect::Explosion));
+}
+
+// The ship is spawned by the player plugin, so the thruster is added once it exists
+fn attach_thruster_emitter(
+    mut commands: Commands,
+    ship_query: Query<Entity, (With<PlayerShip>, Without<ParticleEmitter>)>,
+) {
+    for ship in ship_query.iter() {
+        commands.entity(ship).insert(ParticleEmitter::new(ParticleEffect::Thrust));
+    }
+}
+
+fn lerp_color(start: Color, end: Color, t: f32) -> Color {
+    Color::rgba(
+        start.r() + (end.r() - start.r()) * t,
+        start.g() + (end.g() - start.g()) * t,
+        start.b() + (end.b() - start.b()) * t,
+        start.a() + (end.a() - start.a()) * t,
+    )
+}
+
+// Reuses a dead particle from the emitter's pool when there is one, otherwise spawns a new sprite.
+// Returns false once the global budget is used up.
+fn emit(
+    commands: &mut Commands,
+    budget: &mut ParticleBudget,
+    emitter_entity: Entity,
+    emitter: &mut ParticleEmitter,
+    spec: ParticleSpec,
+) -> bool {
+    if budget.is_full() {
+        return false;
+    }
+    budget.live += 1;
+
+    let particle = Particle {
+        emitter: emitter_entity,
+        velocity: spec.velocity,
+        age: 0.0,
+        lifetime: spec.lifetime,
+        start_size: spec.start_size,
+        end_size: spec.end_size,
+        start_color: spec.start_color,
+        end_color: spec.end_color,
+    };
+    let sprite = Sprite {
+        color: spec.start_color,
+        custom_size: Some(Vec2::new(spec.start_size, spec.start_size)),
+        ..Default::default()
+    };
+
+    match emitter.pool.pop() {
+        Some(entity) => {
+            commands
+                .entity(entity)
+                .insert(particle)
+                .insert(sprite)
+                .insert(Transform::from_translation(spec.position))
+                .insert(Visibility { is_visible: true });
+        }
+        None => {
+            commands
+                .spawn_bundle(SpriteBundle {
+                    sprite,
+                    transform: Transform::from_translation(spec.position),
+                    ..Default::default()
+                })
+                .insert(particle);
+        }
+    }
+    true
+}
+
+fn burst_spec(effect: ParticleEffect, position: Vec3, rng: &mut impl Rng) -> ParticleSpec {
+    let angle = rng.gen_range(0.0..std::f32::consts::TAU);
+    let direction = Vec2::new(angle.cos(), angle.sin());
+
+    match effect {
+        ParticleEffect::Explosion => {
+            let hot = rng.gen_bool(0.6);
+            ParticleSpec {
+                position: position.truncate().extend(0.1),
+                velocity: direction * rng.gen_range(50.0..700.0),
+                lifetime: rng.gen_range(0.6..1.8),
+                start_size: rng.gen_range(6.0..16.0),
+                end_size: 1.0,
+                start_color: if hot { Color::rgb(1.0, 0.9, 0.4) } else { Color::rgb(0.3, 0.6, 1.0) },
+                end_color: if hot { Color::rgba(0.8, 0.1, 0.0, 0.0) } else { Color::rgba(0.1, 0.6, 0.2, 0.0) },
+            }
+        }
+        _ => {
+            let grey = rng.gen_range(0.4..0.7);
+            ParticleSpec {
+                position: position.truncate().extend(0.1),
+                velocity: direction * rng.gen_range(60.0..260.0),
+                lifetime: rng.gen_range(0.3..0.8),
+                start_size: rng.gen_range(3.0..7.0),
+                end_size: 1.0,
+                start_color: Color::rgb(grey, grey * 0.85, grey * 0.7),
+                end_color: Color::rgba(grey * 0.5, grey * 0.4, grey * 0.3, 0.0),
+            }
+        }
+    }
+}
+
+fn emit_collision_debris(
+    mut events: EventReader<CollisionEvent>,
+    mut bursts: EventWriter<ParticleBurstEvent>,
+    transform_query: Query<&Transform>,
+) {
+    for event in events.iter() {
+        if event.is_started() {
+            let (layers_1, layers_2) = event.collision_layers();
+            let player_hit = (is_player(layers_1) && is_enemy(layers_2)) || (is_player(layers_2) && is_enemy(layers_1));
+            let asteroid_hit = is_enemy(layers_1) && is_enemy(layers_2);
+
+            if player_hit || asteroid_hit {
+                let (entity_1, entity_2) = event.rigid_body_entities();
+                if let (Ok(transform_1), Ok(transform_2)) = (transform_query.get(entity_1), transform_query.get(entity_2)) {
+                    bursts.send(ParticleBurstEvent {
+                        effect: ParticleEffect::Debris,
+                        position: (transform_1.translation + transform_2.translation) / 2.,
+                        count: if player_hit { PLAYER_HIT_DEBRIS } else { ASTEROID_HIT_DEBRIS },
+                    });
+                }
+            }
+        }
+    }
+}
+
+fn emit_death_explosion(
+    mut bursts: EventWriter<ParticleBurstEvent>,
+    player_query: Query<(&Transform, &PlayerHealth), (With<Player>, Changed<PlayerHealth>)>,
+) {
+    // Goes off for each player as they go down, not just at the end of the run
+    for (transform, health) in player_query.iter() {
+        if health.0 == 0 {
+            bursts.send(ParticleBurstEvent {
+                effect: ParticleEffect::Explosion,
+                position: transform.translation,
+                count: DEATH_EXPLOSION_PARTICLES,
+            });
+        }
+    }
+}
+
+fn spawn_bursts(
+    mut commands: Commands,
+    mut events: EventReader<ParticleBurstEvent>,
+    mut budget: ResMut<ParticleBudget>,
+    mut emitter_query: Query<(Entity, &mut ParticleEmitter)>,
+) {
+    // Particles are purely visual, so they use their own RNG and leave the game's RandomGenerator sequence alone
+    let mut rng = rand::thread_rng();
+
+    for event in events.iter() {
+        if let Some((emitter_entity, mut emitter)) = emitter_query.iter_mut().find(|(_, emitter)| emitter.effect == event.effect) {
+            for _ in 0..event.count {
+                let spec = burst_spec(event.effect, event.position, &mut rng);
+                if !emit(&mut commands, &mut budget, emitter_entity, &mut emitter, spec) {
+                    break;
+                }
+            }
+        }
+    }
+}
+
+fn emit_thrust(
+    mut commands: Commands,
+    time: Res<Time>,
+    game_paused: Res<GamePaused>,
+    mut budget: ResMut<ParticleBudget>,
+    player_query: Query<(&PlayerId, &Velocity, &Thrusting, &Sprite), With<Player>>,
+    mut ship_query: Query<(Entity, &PlayerId, &Transform, &mut ParticleEmitter), With<PlayerShip>>,
+) {
+    if game_paused.0 {
+        return;
+    }
+
+    let mut rng = rand::thread_rng();
+
+    // Each ship has its own emitter, and gets its thrust from the earth with the same PlayerId
+    for (ship_entity, ship_id, ship_transform, mut emitter) in ship_query.iter_mut() {
+        let (player_velocity, thrusting, player_sprite) = match player_query.iter().find(|(id, _, _, _)| *id == ship_id) {
+            Some((_, velocity, Thrusting(thrusting), sprite)) => (velocity, *thrusting, sprite),
+            None => continue,
+        };
+
+        if !thrusting {
+            emitter.spawn_accumulator = 0.0;
+            continue;
+        }
+
+        let player_size = player_sprite.custom_size.map(|size| size.x).unwrap_or(60.0);
+        // The ship sprite points up, so its facing direction is the rotated Y axis
+        let facing = (ship_transform.rotation * Vec3::Y).truncate();
+        let nozzle = ship_transform.translation.truncate() - facing * player_size * 0.7;
+
+        emitter.spawn_accumulator += time.delta_seconds() * THRUST_PARTICLES_PER_SECOND;
+        while emitter.spawn_accumulator >= 1.0 {
+            emitter.spawn_accumulator -= 1.0;
+
+            let spread: f32 = rng.gen_range(-0.35..0.35);
+            let direction = Vec2::new(
+                -facing.x * spread.cos() + facing.y * spread.sin(),
+                -facing.y * spread.cos() - facing.x * spread.sin(),
+            );
+            let spec = ParticleSpec {
+                position: nozzle.extend(-0.01),
+                velocity: direction * rng.gen_range(150.0..300.0) + player_velocity.linear.truncate() * 0.5,
+                lifetime: rng.gen_range(0.2..0.45),
+                start_size: player_size * rng.gen_range(0.12..0.2),
+                end_size: player_size * 0.05,
+                start_color: Color::rgb(1.0, 0.85, 0.4),
+                end_color: Color::rgba(1.0, 0.2, 0.0, 0.0),
+            };
+            if !emit(&mut commands, &mut budget, ship_entity, &mut emitter, spec) {
+                emitter.spawn_accumulator = 0.0;
+                break;
+            }
+        }
+    }
+}
+
+fn update_particles(
+    time: Res<Time>,
+    game_paused: Res<GamePaused>,
+    mut commands: Commands,
+    mut budget: ResMut<ParticleBudget>,
+    mut particle_query: Query<(Entity, &mut Particle, &mut Transform, &mut Sprite, &mut Visibility)>,
+    mut emitter_query: Query<&mut ParticleEmitter>,
+) {
+    if game_paused.0 {
+        return;
+    }
+
+    let delta = time.delta_seconds();
+
+    for (entity, mut particle, mut transform, mut sprite, mut visibility) in particle_query.iter_mut() {
+        if !visibility.is_visible {
+            // Pooled particles go along with their emitter, like a second player's ship at the end of a run
+            if emitter_query.get_mut(particle.emitter).is_err() {
+                commands.entity(entity).despawn();
+            }
+            continue;
+        }
+
+        particle.age += delta;
+        if particle.age >= particle.lifetime {
+            visibility.is_visible = false;
+            budget.live = budget.live.saturating_sub(1);
+
+            match emitter_query.get_mut(particle.emitter) {
+                Ok(mut emitter) => emitter.pool.push(entity),
+                // The emitter went away, so there's no pool to go back to
+                Err(_) => commands.entity(entity).despawn(),
+            }
+            continue;
+        }
+
+        let t = particle.age / particle.lifetime;
+        transform.translation += (particle.velocity * delta).extend(0.0);
+        particle.velocity *= 1.0 - (2.0 * delta).min(1.0);
+
+        let size = particle.start_size + (particle.end_size - particle.start_size) * t;
+        sprite.custom_size = Some(Vec2::new(size, size));
+        sprite.color = lerp_color(particle.start_color, particle.end_color, t);
+    }
+}
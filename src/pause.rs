@@ -0,0 +1,138 @@
+use bevy::prelude::*;
+use bevy::window::{WindowFocused, WindowResized};
+use heron::prelude::*;
+
+use crate::menu::*;
+use crate::types::*;
+
+pub struct PausePlugin;
+
+impl Plugin for PausePlugin {
+    fn build(&self, app: &mut App) {
+        app
+            .add_system_set(
+                SystemSet::on_update(AppState::InGame)
+                    .with_system(toggle_physics_pause.after("menu_navigation"))
+                    .with_system(auto_pause)
+                    .with_system(sync_pause_menu)
+                    .with_system(pause_menu_actions.label("pause_menu_actions").after("menu_navigation"))
+            )
+            // Opening the options from the pause menu covers the game, so the pause menu is rebuilt by sync_pause_menu afterwards
+            .add_system_set(SystemSet::on_pause(AppState::InGame).with_system(despawn_menu_screens))
+            .add_system_set(SystemSet::on_exit(AppState::InGame).with_system(despawn_menu_screens));
+    }
+}
+
+// Marks the menu screen shown while the game is paused
+#[derive(Component)]
+struct PauseMenu;
+
+// Need to add timers to this as they are added to the game.
+// Also important. Need to check GamePaused flag in other systems before applying changes.
+pub fn set_paused(
+    paused: bool,
+    physics_time: &mut PhysicsTime,
+    enemy_spawn_timer: &mut SpawnTimer,
+    game_paused: &mut GamePaused,
+) {
+    if paused {
+        physics_time.pause();
+        enemy_spawn_timer.0.pause();
+    } else {
+        physics_time.resume();
+        enemy_spawn_timer.0.unpause();
+    }
+    game_paused.0 = paused;
+}
+
+// Unpausing is done through the pause menu, which reads Space and Escape itself while it is open
+fn toggle_physics_pause(
+    input: Res<Input<KeyCode>>,
+    mut physics_time: ResMut<PhysicsTime>,
+    mut game_paused: ResMut<GamePaused>,
+    mut enemy_spawn_timer: ResMut<SpawnTimer>,
+    player_died: Res<PlayerDied>,
+) {
+    if !player_died.0 && !game_paused.0 && (input.just_pressed(KeyCode::Space) || input.just_pressed(KeyCode::Escape)) {
+        set_paused(true, &mut physics_time, &mut enemy_spawn_timer, &mut game_paused);
+    }
+}
+
+// Pauses when the window loses focus or is minimised, which shows up as a resize to zero.
+// Not online though, where it would also stop the game for the other player (or both, when testing two windows side by side).
+fn auto_pause(
+    sim: Res<SimStep>,
+    mut focus_events: EventReader<WindowFocused>,
+    mut resize_events: EventReader<WindowResized>,
+    mut physics_time: ResMut<PhysicsTime>,
+    mut game_paused: ResMut<GamePaused>,
+    mut enemy_spawn_timer: ResMut<SpawnTimer>,
+    player_died: Res<PlayerDied>,
+) {
+    let lost_focus = focus_events.iter().filter(|event| !event.focused).count() > 0;
+    let minimised = resize_events.iter().filter(|event| event.width == 0. || event.height == 0.).count() > 0;
+
+    if (lost_focus || minimised) && !player_died.0 && !game_paused.0 && !sim.lockstep {
+        set_paused(true, &mut physics_time, &mut enemy_spawn_timer, &mut game_paused);
+    }
+}
+
+fn sync_pause_menu(
+    mut commands: Commands,
+    font: Res<BoldFont>,
+    locale: Res<Locale>,
+    game_paused: Res<GamePaused>,
+    screen_query: Query<Entity, With<PauseMenu>>,
+) {
+    let open = screen_query.iter().next().is_some();
+
+    if game_paused.0 && !open {
+        let root = spawn_menu_root(&mut commands);
+        commands.entity(root).insert(PauseMenu).with_children(|parent| {
+            spawn_menu_title(parent, &font.0, &locale, "pause.title");
+            spawn_localized_button(parent, &font.0, &locale, "pause.resume", 0, MenuAction::Resume, true);
+            spawn_localized_button(parent, &font.0, &locale, "menu.restart", 1, MenuAction::Restart, true);
+            spawn_localized_button(parent, &font.0, &locale, "menu.options", 2, MenuAction::Options, true);
+            spawn_localized_button(parent, &font.0, &locale, "pause.quit_to_menu", 3, MenuAction::QuitToMenu, true);
+        });
+    } else if !game_paused.0 && open {
+        for screen in screen_query.iter() {
+            commands.entity(screen).despawn_recursive();
+        }
+    }
+}
+
+fn pause_menu_actions(
+    mut actions: EventReader<MenuActionEvent>,
+    mut state: ResMut<State<AppState>>,
+    mut restart_requests: EventWriter<RestartRequestEvent>,
+    mut physics_time: ResMut<PhysicsTime>,
+    mut game_paused: ResMut<GamePaused>,
+    mut enemy_spawn_timer: ResMut<SpawnTimer>,
+    screen_query: Query<(), With<PauseMenu>>,
+) {
+    // The pause menu is only rebuilt once the options screen has closed, so the Back that closed it isn't read as Resume
+    let open = screen_query.iter().next().is_some();
+
+    for MenuActionEvent(action) in actions.iter() {
+        // The same buttons show up on the game over screen, which handles them itself
+        if !game_paused.0 || !open {
+            continue;
+        }
+
+        match action {
+            MenuAction::Resume | MenuAction::Back => {
+                set_paused(false, &mut physics_time, &mut enemy_spawn_timer, &mut game_paused);
+            }
+            // Starting a run unpauses everything
+            MenuAction::Restart => restart_requests.send(RestartRequestEvent),
+            MenuAction::Options => {
+                let _ = state.push(AppState::Options);
+            }
+            MenuAction::QuitToMenu => {
+                let _ = state.set(AppState::MainMenu);
+            }
+            _ => {}
+        }
+    }
+}
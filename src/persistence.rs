@@ -0,0 +1,63 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use bevy::prelude::*;
+use serde::{de::DeserializeOwned, Serialize};
+
+// Everything the game saves between sessions lives in its own folder in the user's config directory.
+// There is no config directory on the web build, so nothing is saved there.
+pub fn config_path(file_name: &str) -> Option<PathBuf> {
+    dirs::config_dir().map(|dir| dir.join("earth_escape").join(file_name))
+}
+
+// Missing or unreadable files just mean the defaults get used, so this never fails loudly
+pub fn load<T: DeserializeOwned>(file_name: &str) -> Option<T> {
+    load_path(&config_path(file_name)?)
+}
+
+// For files from outside the config folder, like a ghost from a friend
+pub fn load_path<T: DeserializeOwned>(path: &Path) -> Option<T> {
+    let contents = fs::read_to_string(path).ok()?;
+
+    match ron::from_str(&contents) {
+        Ok(value) => Some(value),
+        Err(error) => {
+            warn!("Ignoring {}: {}", path.display(), error);
+            None
+        }
+    }
+}
+
+pub fn save<T: Serialize>(file_name: &str, value: &T) {
+    let path = match config_path(file_name) {
+        Some(path) => path,
+        None => return,
+    };
+
+    let result = ron::ser::to_string_pretty(value, ron::ser::PrettyConfig::default())
+        .map_err(|error| error.to_string())
+        .and_then(|contents| {
+            if let Some(parent) = path.parent() {
+                fs::create_dir_all(parent).map_err(|error| error.to_string())?;
+            }
+            fs::write(&path, contents).map_err(|error| error.to_string())
+        });
+
+    if let Err(error) = result {
+        warn!("Failed to save {}: {}", path.display(), error);
+    }
+}
+
+pub fn exists(file_name: &str) -> bool {
+    config_path(file_name).is_some_and(|path| path.exists())
+}
+
+pub fn delete(file_name: &str) {
+    if let Some(path) = config_path(file_name) {
+        if path.exists() {
+            if let Err(error) = fs::remove_file(&path) {
+                warn!("Failed to delete {}: {}", path.display(), error);
+            }
+        }
+    }
+}
@@ -5,24 +5,87 @@ use heron::prelude::*;
 use crate::utilities::*;
 use crate::types::*;
 
+// In world units, which stay the same whatever the window size
+pub const PLAYER_SIZE: f32 = 60.0;
+pub const MAX_HEALTH: u8 = 5;
+
+// Player two is tinted so the two earths (and their hearts) can be told apart
+const PLAYER_TINTS: [Color; 2] = [Color::WHITE, Color::rgb(0.55, 0.8, 1.0)];
+const DOWNED_COLOR: Color = Color::rgb(0.35, 0.35, 0.35);
+
+// A downed player gets back up once the other player has stayed this close for REVIVE_SECONDS
+const REVIVE_RADIUS: f32 = PLAYER_SIZE * 2.0;
+const REVIVE_SECONDS: f32 = 3.0;
+const REVIVE_HEALTH: u8 = 2;
+
+// Gamepad sticks rarely rest exactly at zero
+const STICK_DEADZONE: f32 = 0.2;
+
 pub struct PlayerPlugin;
 
 impl Plugin for PlayerPlugin {
     fn build(&self, app: &mut App) {
         app
+            .insert_resource(PlayerCount(1))
+            .insert_resource(GodMode(false))
             .add_startup_system(setup)
             .add_startup_system(add_player)
-            .add_system(player_movement)
-            .add_system(calculate_health);
+            .add_system(show_downed_players)
+            .add_system_set(
+                SystemSet::on_update(AppState::InGame)
+                    .with_system(setup_players_for_run.after("start_run"))
+                    .with_system(player_movement.label("player_movement"))
+                    .with_system(calculate_health.label("calculate_health"))
+                    .with_system(revive_players.after("calculate_health"))
+            );
     }
 }
 
+// The node at the top of the screen that holds each player's row of hearts
+#[derive(Component)]
+struct HeartRows;
+
+fn player_tint(id: PlayerId) -> Color {
+    PLAYER_TINTS[id.0 % PLAYER_TINTS.len()]
+}
+
+// One player's row of hearts, inside the HeartRows node
+fn spawn_heart_row(parent: &mut ChildBuilder, full_heart_sprite: &Handle<Image>, id: PlayerId) -> Entity {
+    parent
+        .spawn_bundle(NodeBundle {
+            style: Style {
+                justify_content: JustifyContent::SpaceBetween,
+                align_items: AlignItems::Center,
+                // Keeps the two rows apart in co-op
+                margin: Rect {
+                    left: Px(16.0),
+                    right: Px(16.0),
+                    ..Default::default()
+                },
+                ..Default::default()
+            },
+            color: Color::NONE.into(),
+            ..Default::default()
+        })
+        .with_children(|row| {
+            for index in 0..MAX_HEALTH {
+                row
+                    .spawn_bundle(ImageBundle {
+                        image: full_heart_sprite.clone().into(),
+                        color: player_tint(id).into(),
+                        ..Default::default()
+                    })
+                    .insert(HeartSprite(index))
+                    .insert(id);
+            }
+        })
+        .id()
+}
+
 fn setup(
-    mut commands: Commands, 
+    mut commands: Commands,
     asset_server: Res<AssetServer>,
 ) {
-    commands.insert_resource(PlayerDied(false));
-
     let full_heart_sprite: Handle<Image> = asset_server.load("sprites/full_heart.png");
     let empty_heart_sprite: Handle<Image> = asset_server.load("sprites/empty_heart.png");
 
@@ -46,82 +109,42 @@ fn setup(
             color: Color::NONE.into(),
             ..Default::default()
         })
+        .insert(HeartRows)
         .with_children(|parent| {
-            parent
-                .spawn_bundle(NodeBundle {
-                    style: Style {
-                        justify_content: JustifyContent::SpaceBetween,
-                        align_items: AlignItems::Center,
-
-                        ..Default::default()
-                    },
-                    color: Color::NONE.into(),
-                    ..Default::default()
-                })
-                .with_children(|nested_parent| {
-                    nested_parent
-                        .spawn_bundle(ImageBundle {
-                            image: full_heart_sprite.clone().into(),
-                            ..Default::default()
-                        })
-                        .insert(HeartSprite(0));
-                    
-                    nested_parent
-                        .spawn_bundle(ImageBundle {
-                            image: full_heart_sprite.clone().into(),
-                            ..Default::default()
-                        })
-                        .insert(HeartSprite(1));
-
-                    nested_parent
-                        .spawn_bundle(ImageBundle {
-                            image: full_heart_sprite.clone().into(),
-                            ..Default::default()
-                        })
-                        .insert(HeartSprite(2));
-
-                    nested_parent
-                        .spawn_bundle(ImageBundle {
-                            image: full_heart_sprite.clone().into(),
-                            ..Default::default()
-                        })
-                        .insert(HeartSprite(3));
-
-                    nested_parent
-                        .spawn_bundle(ImageBundle {
-                            image: full_heart_sprite.clone().into(),
-                            ..Default::default()
-                        })
-                        .insert(HeartSprite(4));
-                });
-            
+            spawn_heart_row(parent, &full_heart_sprite, PlayerId(0));
         });
 }
 
-fn add_player(
-    mut commands: Commands,
-    windows: Res<Windows>,
-    asset_server: Res<AssetServer>,
-    mut texture_atlases: ResMut<Assets<TextureAtlas>>,
-) {
-    let size = windows.get_primary().unwrap().width() / 20.;
-
-    commands
+// Spawns an earth and the ship drawn over it, and returns both
+fn spawn_player(
+    commands: &mut Commands,
+    asset_server: &AssetServer,
+    texture_atlases: &mut Assets<TextureAtlas>,
+    id: PlayerId,
+    position: Vec2,
+    input: PlayerInput,
+) -> (Entity, Entity) {
+    let size = PLAYER_SIZE;
+
+    let player = commands
         .spawn_bundle(
             SpriteBundle {
                 sprite: Sprite {
+                    color: player_tint(id),
                     custom_size: Some(Vec2::new(size, size)),
                     ..Default::default()
                 },
                 texture: asset_server.load("sprites/PlayerEarth.png"),
-                transform: Transform::from_xyz(0.0, 0.0, 0.0),
+                transform: Transform::from_xyz(position.x, position.y, 0.0),
                 ..Default::default()
             }
         )
         .insert(Player)
+        .insert(id)
+        .insert(input)
         .insert(Speed(5.0))
         .insert(RigidBody::Dynamic)
-        
+
         // Attach a collision shape
         // .insert(CollisionShape::Cuboid {
         //     half_extends: Vec3::new(size / 2., size / 2., 0.0),
@@ -131,8 +154,8 @@ fn add_player(
             radius: size / 2.,
         })
 
-        
-        
+
+
         // Optionally add other useful components...
         .insert(Velocity::default())
         // .insert(Acceleration::from_linear(Vec3::X * 1.0))
@@ -140,16 +163,23 @@ fn add_player(
         .insert(Damping::from_linear(0.5).with_angular(1.0))
         .insert(RotationConstraints::lock())
         .insert(CollisionLayers::new(Layer::Player, Layer::Enemies))
-        .insert(PlayerHealth(5));
+        .insert(PlayerHealth(MAX_HEALTH))
+        .insert(ReviveProgress::default())
+        .insert(Thrusting(false))
+        .id();
 
     let ship_texture_atlas = TextureAtlas::from_grid(asset_server.load("sprites/Space_Ship_Spritesheet.png"), Vec2::new(96.0, 96.0), 2, 1);
     let texture_atlas_handle = texture_atlases.add(ship_texture_atlas);
 
-    commands
+    let ship = commands
         .spawn_bundle(
             SpriteSheetBundle {
                 texture_atlas: texture_atlas_handle,
-                transform: Transform::from_scale(Vec3::new(size / 45., size / 45., 1.)),
+                transform: Transform {
+                    translation: position.extend(0.0),
+                    scale: Vec3::new(size / 45., size / 45., 1.),
+                    ..Default::default()
+                },
                 sprite: TextureAtlasSprite {
                     index: 0,
                     ..Default::default()
@@ -157,139 +187,285 @@ fn add_player(
                 ..Default::default()
             }
         )
-        .insert(PlayerShip);
+        .insert(PlayerShip)
+        .insert(id)
+        .id();
+
+    (player, ship)
+}
+
+fn add_player(
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+    mut texture_atlases: ResMut<Assets<TextureAtlas>>,
+) {
+    let input = PlayerInput {
+        controls: None,
+        gamepad: 0,
+        network: None,
+    };
+    let (player, ship) = spawn_player(&mut commands, &asset_server, &mut texture_atlases, PlayerId(0), Vec2::ZERO, input);
+    commands.entity(player).insert(PlayerOne);
+    commands.entity(ship).insert(PlayerOne);
+}
+
+// Player two only exists during co-op runs. Its earth, ship and hearts are RunScoped, so they go away with the asteroids.
+fn setup_players_for_run(
+    mut commands: Commands,
+    mut events: EventReader<StartRunEvent>,
+    player_count: Res<PlayerCount>,
+    asset_server: Res<AssetServer>,
+    mut texture_atlases: ResMut<Assets<TextureAtlas>>,
+    full_heart_sprite: Res<FullHeartSprite>,
+    sim: Res<SimStep>,
+    rows_query: Query<Entity, With<HeartRows>>,
+    mut input_query: Query<&mut PlayerInput, With<PlayerOne>>,
+) {
+    if events.iter().count() > 0 {
+        let coop = player_count.0 > 1;
+        // Online, both players are moved by the session from the first tick on
+        let network = if sim.lockstep { Some(Vec2::ZERO) } else { None };
+
+        // Two players on one keyboard get half of it each
+        let mut player_one_input = input_query.single_mut();
+        player_one_input.controls = if coop { Some(ControlScheme::Wasd) } else { None };
+        player_one_input.network = network;
+
+        if coop {
+            let input = PlayerInput {
+                controls: Some(ControlScheme::Arrows),
+                gamepad: 1,
+                network,
+            };
+            let (player, ship) = spawn_player(&mut commands, &asset_server, &mut texture_atlases, PlayerId(1), Vec2::new(PLAYER_SIZE * 2.0, 0.0), input);
+            commands.entity(player).insert(RunScoped);
+            commands.entity(ship).insert(RunScoped);
+
+            let mut row = None;
+            commands.entity(rows_query.single()).with_children(|parent| {
+                row = Some(spawn_heart_row(parent, &full_heart_sprite.0, PlayerId(1)));
+            });
+            if let Some(row) = row {
+                commands.entity(row).insert(RunScoped);
+            }
+        }
+    }
+}
+
+fn stick_axis(gamepad_axes: &Axis<GamepadAxis>, gamepad: Gamepad, axis_type: GamepadAxisType) -> f32 {
+    let value = gamepad_axes.get(GamepadAxis(gamepad, axis_type)).unwrap_or(0.0);
+    if value.abs() < STICK_DEADZONE { 0.0 } else { value }
+}
+
+// Keyboard, d-pad and left stick all add up, clamped so holding several doesn't go any faster.
+// Also read by the online session for the local player.
+pub fn input_direction(
+    keyboard_input: &Input<KeyCode>,
+    gamepad_buttons: &Input<GamepadButton>,
+    gamepad_axes: &Axis<GamepadAxis>,
+    controls: ControlScheme,
+    gamepad: usize,
+) -> Vec2 {
+    let gamepad = Gamepad(gamepad);
+    let button = |button_type| gamepad_buttons.pressed(GamepadButton(gamepad, button_type));
+
+    let mut x = stick_axis(gamepad_axes, gamepad, GamepadAxisType::LeftStickX);
+    let mut y = stick_axis(gamepad_axes, gamepad, GamepadAxisType::LeftStickY);
+
+    if controls.pressed(keyboard_input, KeyCode::A, KeyCode::Left) || button(GamepadButtonType::DPadLeft) {
+        x -= 1.0;
+    };
+    if controls.pressed(keyboard_input, KeyCode::D, KeyCode::Right) || button(GamepadButtonType::DPadRight) {
+        x += 1.0;
+    };
+    if controls.pressed(keyboard_input, KeyCode::W, KeyCode::Up) || button(GamepadButtonType::DPadUp) {
+        y += 1.0;
+    };
+    if controls.pressed(keyboard_input, KeyCode::S, KeyCode::Down) || button(GamepadButtonType::DPadDown) {
+        y -= 1.0;
+    };
+
+    Vec2::new(x.clamp(-1.0, 1.0), y.clamp(-1.0, 1.0))
 }
 
 fn player_movement(
     game_paused: Res<GamePaused>,
+    sim: Res<SimStep>,
     keyboard_input: Res<Input<KeyCode>>,
-    mut query: Query<(&Transform, &Speed, &mut Velocity), (With<Player>, (Without<Camera2D>, Without<PlayerShip>))>,
-    mut ship_query: Query<(&mut Transform, &mut TextureAtlasSprite), (With<PlayerShip>, (Without<Camera2D>, Without<Player>))>,
-    mut camera_query: Query<&mut Transform, (With<Camera2D>, (Without<Player>, Without<PlayerShip>))>,
-    player_died: Res<PlayerDied>,
+    gamepad_buttons: Res<Input<GamepadButton>>,
+    gamepad_axes: Res<Axis<GamepadAxis>>,
+    mut query: Query<(&PlayerId, &PlayerInput, &PlayerHealth, &Transform, &Speed, &mut Velocity, &mut Thrusting), (With<Player>, (Without<Camera2D>, Without<PlayerShip>))>,
+    mut ship_query: Query<(&PlayerId, &mut Transform, &mut TextureAtlasSprite), (With<PlayerShip>, (Without<Camera2D>, Without<Player>))>,
+    controls: Res<ControlScheme>,
     // mut touches: EventReader<TouchInput>,
     // windows: Res<Windows>,
-) 
+)
 {
-    if !game_paused.0 {
-        let (transform, Speed(speed), mut velocity) = query.single_mut();
+    if !game_paused.0 && !sim.is_stalled() {
+        for (id, input, health, transform, Speed(speed), mut velocity, mut thrusting) in query.iter_mut() {
+            // A downed player drifts until they're revived
+            let direction =
+                if health.0 == 0 {
+                    Vec2::ZERO
+                } else if let Some(direction) = input.network {
+                    direction
+                } else {
+                    input_direction(&keyboard_input, &gamepad_buttons, &gamepad_axes, input.controls.unwrap_or(*controls), input.gamepad)
+                };
+            let input_active = direction != Vec2::ZERO;
 
-        camera_query.single_mut().translation = transform.translation;
+            velocity.linear.x += direction.x * speed;
+            velocity.linear.y += direction.y * speed;
 
-        let mut input_active = false;
+            thrusting.0 = input_active;
 
-        if !player_died.0 {
-            let mut x = 0.0;
-            let mut y = 0.0;
+            for (ship_id, mut ship_transform, mut ship_image) in ship_query.iter_mut() {
+                if ship_id != id {
+                    continue;
+                }
 
-            // if let Some(touch) = touches.iter().next() {
-            //     let window_width = windows.get_primary().unwrap().width();
-            //     x += (touch.position.x - window_width / 2.) / (window_width / 2.);
-            //     y += (touch.position.y - window_width / 2.) / (window_width / 2.);
-            // } else {
+                ship_image.index =
+                    if input_active {
+                        0
+                    } else {
+                        1
+                    };
 
-                
-            if keyboard_input.pressed(KeyCode::A) || keyboard_input.pressed(KeyCode::Left) {
-                x -= 1.0;
-                input_active = true;
-            };
-            if keyboard_input.pressed(KeyCode::D) || keyboard_input.pressed(KeyCode::Right) {
-                x += 1.0;
-                input_active = true;
-            };
-            if keyboard_input.pressed(KeyCode::W) || keyboard_input.pressed(KeyCode::Up) {
-                y += 1.0;
-                input_active = true;
-            };
-            if keyboard_input.pressed(KeyCode::S) || keyboard_input.pressed(KeyCode::Down) {
-                y -= 1.0;
-                input_active = true;
-            };
-            // }
+                // Can also just use velocity.linear as the diff because ship_transform gets updated to transform every tick, but this method seems to give a nice result
+                let diff = (transform.translation + velocity.linear) - ship_transform.translation;
+                let new_angle = diff.y.atan2(diff.x);
 
-            velocity.linear.x += x * speed;
-            velocity.linear.y += y * speed;
+                ship_transform.rotation = Quat::from_axis_angle(Vec3::new(0., 0., 1.), new_angle - std::f32::consts::FRAC_PI_2);
 
-            // transform.translation.x += x * speed;
-            // transform.translation.y += y * speed;
+                ship_transform.translation = transform.translation;
+            }
         }
-
-        let (mut ship_transform, mut ship_image) = ship_query.single_mut();
-
-        ship_image.index =
-            if input_active {
-                0
-            } else {
-                1
-            };
-
-        // Can also just use velocity.linear as the diff because ship_transform gets updated to transform every tick, but this method seems to give a nice result
-        let diff = (transform.translation + velocity.linear) - ship_transform.translation;
-        let new_angle = diff.y.atan2(diff.x);
-
-        ship_transform.rotation = Quat::from_axis_angle(Vec3::new(0., 0., 1.), new_angle - std::f32::consts::FRAC_PI_2);
-
-        ship_transform.translation = transform.translation;
     }
 }
 
 fn calculate_health(
     mut events: EventReader<CollisionEvent>,
     mut player_died: ResMut<PlayerDied>,
-    mut heart_query: Query<(&mut UiImage, &HeartSprite), Without<PlayerHealth>>,
+    god_mode: Res<GodMode>,
+    mut heart_query: Query<(&mut UiImage, &HeartSprite, &PlayerId), Without<PlayerHealth>>,
     full_heart_sprite: Res<FullHeartSprite>,
     empty_heart_sprite: Res<EmptyHeartSprite>,
-    mut health_query: Query<&mut PlayerHealth>,
-    mut center_text: Query<&mut Text, With<CenterMessageText>>,
-    mut sub_center_text: Query<&mut Text, (With<SubCenterText>, Without<CenterMessageText>)>,
+    mut health_query: Query<(&mut PlayerHealth, &PlayerId)>,
     mut enemy_spawn_timer: ResMut<SpawnTimer>,
-) 
+)
 {
     if !player_died.0 {
-        let mut health = health_query.single_mut();
-
         events
             .iter()
             .for_each(|event| {
+                let (layers_1, layers_2) = event.collision_layers();
+                let (entity_1, entity_2) = event.rigid_body_entities();
+
+                let player =
+                    if is_player(layers_1) && is_enemy(layers_2) {
+                        entity_1
+                    } else if is_player(layers_2) && is_enemy(layers_1) {
+                        entity_2
+                    } else {
+                        return;
+                    };
+
+                if god_mode.0 {
+                    return;
+                }
 
-                if event.is_stopped() {
-                    let (layers_1, layers_2) = event.collision_layers();
-                    if health.0 < 5 {
-                        if is_player(layers_1) && is_enemy(layers_2) {
-                            health.0 += 1;
-                        } else if is_player(layers_2) && is_enemy(layers_1) {
-                            health.0 += 1;
-                        }
+                if let Ok((mut health, _)) = health_query.get_mut(player) {
+                    // Down is down until the other player comes over
+                    if health.0 == 0 {
+                        return;
                     }
-                }
-                if event.is_started() {
-                    if health.0 > 0 {
-                        let (layers_1, layers_2) = event.collision_layers();
-                        if is_player(layers_1) && is_enemy(layers_2) {
-                            health.0 -= 1;
-                        } else if is_player(layers_2) && is_enemy(layers_1) {
-                            health.0 -= 1;
-                        }
+
+                    if event.is_stopped() && health.0 < MAX_HEALTH {
+                        health.0 += 1;
+                    }
+                    if event.is_started() {
+                        health.0 -= 1;
                     }
                 }
             });
 
-        if health.0 <= 0 {
+        // The run only ends once there's nobody left to do the reviving
+        if health_query.iter().all(|(health, _)| health.0 == 0) {
             player_died.0 = true;
-            center_text.single_mut().sections[0].value = String::from("You Died");
-            sub_center_text.single_mut().sections[0].value = String::from("Press R to restart");
             enemy_spawn_timer.0.pause();
-            for (mut sprite, _) in heart_query.iter_mut() {
+        }
+
+        for (mut sprite, HeartSprite(index), owner) in heart_query.iter_mut() {
+            let health = health_query
+                .iter()
+                .find(|(_, id)| *id == owner)
+                .map(|(health, _)| health.0)
+                .unwrap_or(0);
+
+            if health > *index {
+                sprite.0 = full_heart_sprite.0.clone();
+            } else {
                 sprite.0 = empty_heart_sprite.0.clone();
             }
-        } else {
-            for (mut sprite, HeartSprite(id)) in heart_query.iter_mut() {
-                if health.0 > *id {
-                    sprite.0 = full_heart_sprite.0.clone();
-                } else {
-                    sprite.0 = empty_heart_sprite.0.clone();
-                }
+        }
+    }
+}
+
+fn revive_players(
+    sim: Res<SimStep>,
+    game_paused: Res<GamePaused>,
+    player_died: Res<PlayerDied>,
+    mut player_query: Query<(&Transform, &mut PlayerHealth, &mut ReviveProgress), With<Player>>,
+) {
+    if game_paused.0 || player_died.0 {
+        return;
+    }
+
+    let living: Vec<Vec2> = player_query
+        .iter()
+        .filter(|(_, health, _)| health.0 > 0)
+        .map(|(transform, _, _)| transform.translation.truncate())
+        .collect();
+
+    for (transform, mut health, mut progress) in player_query.iter_mut() {
+        if health.0 > 0 {
+            continue;
+        }
+
+        let position = transform.translation.truncate();
+        if living.iter().any(|other| other.distance(position) <= REVIVE_RADIUS) {
+            progress.0 += sim.delta_seconds();
+            if progress.0 >= REVIVE_SECONDS {
+                health.0 = REVIVE_HEALTH;
+                progress.0 = 0.0;
             }
+        } else if progress.0 > 0.0 {
+            // Wandering off starts the revive over
+            progress.0 = 0.0;
         }
     }
-}
\ No newline at end of file
+}
+
+// Downed earths go grey, and get their colour back as the revive goes on
+fn show_downed_players(
+    mut player_query: Query<(&PlayerId, &PlayerHealth, &ReviveProgress, &mut Sprite), With<Player>>,
+) {
+    for (id, health, progress, mut sprite) in player_query.iter_mut() {
+        let tint = player_tint(*id);
+        let color =
+            if health.0 > 0 {
+                tint
+            } else {
+                let t = (progress.0 / REVIVE_SECONDS).min(1.0);
+                Color::rgb(
+                    DOWNED_COLOR.r() + (tint.r() - DOWNED_COLOR.r()) * t,
+                    DOWNED_COLOR.g() + (tint.g() - DOWNED_COLOR.g()) * t,
+                    DOWNED_COLOR.b() + (tint.b() - DOWNED_COLOR.b()) * t,
+                )
+            };
+
+        if sprite.color != color {
+            sprite.color = color;
+        }
+    }
+}
@@ -0,0 +1,161 @@
+use bevy::prelude::*;
+use bevy::ui::Val::Px;
+
+use crate::types::*;
+use crate::utilities::center_of;
+
+// Size of the radar widget on screen, in pixels
+const RADAR_SIZE: f32 = 180.0;
+const PLAYER_BLIP_SIZE: f32 = 6.0;
+
+pub struct RadarPlugin;
+
+impl Plugin for RadarPlugin {
+    fn build(&self, app: &mut App) {
+        app
+            .insert_resource(RadarSettings::default())
+            .insert_resource(RadarBlipPool::default())
+            .add_startup_system(setup)
+            .add_system(update_radar);
+    }
+}
+
+// The radar's background node, which the blips are placed inside
+#[derive(Component)]
+struct RadarPanel;
+
+// A dot on the radar for one asteroid or RadarTarget
+#[derive(Component)]
+struct RadarBlip;
+
+// Blip nodes are reused between frames instead of being respawned, since there can be up to a thousand asteroids
+#[derive(Default)]
+struct RadarBlipPool(Vec<Entity>);
+
+fn setup(
+    mut commands: Commands,
+) {
+    commands
+        .spawn_bundle(NodeBundle {
+            style: Style {
+                size: Size::new(Px(RADAR_SIZE), Px(RADAR_SIZE)),
+                position_type: PositionType::Absolute,
+                position: Rect {
+                    top: Px(16.0),
+                    right: Px(16.0),
+                    ..Default::default()
+                },
+                ..Default::default()
+            },
+            color: Color::rgba(0.0, 0.15, 0.05, 0.6).into(),
+            ..Default::default()
+        })
+        .insert(RadarPanel)
+        .with_children(|parent| {
+            parent.spawn_bundle(NodeBundle {
+                style: Style {
+                    size: Size::new(Px(PLAYER_BLIP_SIZE), Px(PLAYER_BLIP_SIZE)),
+                    position_type: PositionType::Absolute,
+                    position: Rect {
+                        left: Px((RADAR_SIZE - PLAYER_BLIP_SIZE) / 2.),
+                        bottom: Px((RADAR_SIZE - PLAYER_BLIP_SIZE) / 2.),
+                        ..Default::default()
+                    },
+                    ..Default::default()
+                },
+                color: Color::rgb(0.3, 0.6, 1.0).into(),
+                ..Default::default()
+            });
+        });
+}
+
+// Small asteroids are yellow, the biggest ones are red
+fn blip_color(size_scale: f32) -> Color {
+    let t = ((size_scale - 0.75) / 2.0).clamp(0.0, 1.0);
+    Color::rgb(1.0, 0.9 * (1.0 - t), 0.1)
+}
+
+fn target_color(kind: &RadarTarget) -> Color {
+    match kind {
+        RadarTarget::Pickup => Color::GREEN,
+        RadarTarget::Objective => Color::CYAN,
+    }
+}
+
+fn update_radar(
+    mut commands: Commands,
+    settings: Res<RadarSettings>,
+    mut pool: ResMut<RadarBlipPool>,
+    panel_query: Query<Entity, With<RadarPanel>>,
+    player_query: Query<&Transform, With<Player>>,
+    chaser_query: Query<(&Transform, &SizeScale), (With<ChasingEnemy>, Without<Player>)>,
+    target_query: Query<(&Transform, &RadarTarget), (Without<ChasingEnemy>, Without<Player>)>,
+    mut blip_query: Query<(&mut Style, &mut UiColor), With<RadarBlip>>,
+) {
+    // Centred between the players in co-op, same as the camera
+    let player_position = match center_of(player_query.iter().map(|transform| transform.translation.truncate())) {
+        Some(center) => center,
+        None => return,
+    };
+    let half_size = RADAR_SIZE / 2.;
+
+    // Pickups and objectives go last so they are drawn on top of any asteroids
+    let blips = chaser_query
+        .iter()
+        .map(|(transform, SizeScale(size_scale))| (transform.translation.truncate(), 3.0 + 2.0 * size_scale, blip_color(*size_scale)))
+        .chain(target_query.iter().map(|(transform, kind)| (transform.translation.truncate(), 8.0, target_color(kind))))
+        .filter_map(|(position, size, color)| {
+            let offset = position - player_position;
+            if offset.length() > settings.range {
+                None
+            } else {
+                Some((offset / settings.range * half_size, size, color))
+            }
+        })
+        .take(settings.max_blips);
+
+    let mut used = 0;
+    for (offset, size, color) in blips {
+        let left = Px(half_size + offset.x - size / 2.);
+        let bottom = Px(half_size + offset.y - size / 2.);
+
+        if let Some(blip) = pool.0.get(used) {
+            if let Ok((mut style, mut ui_color)) = blip_query.get_mut(*blip) {
+                style.display = Display::Flex;
+                style.position.left = left;
+                style.position.bottom = bottom;
+                style.size = Size::new(Px(size), Px(size));
+                ui_color.0 = color;
+            }
+        } else {
+            let blip = commands
+                .spawn_bundle(NodeBundle {
+                    style: Style {
+                        size: Size::new(Px(size), Px(size)),
+                        position_type: PositionType::Absolute,
+                        position: Rect {
+                            left,
+                            bottom,
+                            ..Default::default()
+                        },
+                        ..Default::default()
+                    },
+                    color: color.into(),
+                    ..Default::default()
+                })
+                .insert(RadarBlip)
+                .id();
+            commands.entity(panel_query.single()).push_children(&[blip]);
+            pool.0.push(blip);
+        }
+        used += 1;
+    }
+
+    for blip in pool.0.iter().skip(used) {
+        if let Ok((mut style, _)) = blip_query.get_mut(*blip) {
+            if style.display != Display::None {
+                style.display = Display::None;
+            }
+        }
+    }
+}
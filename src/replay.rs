@@ -0,0 +1,335 @@
+use bevy::prelude::*;
+use bevy::ui::Val::Px;
+
+use crate::types::*;
+
+// Snapshots per second. Positions in between are interpolated during playback.
+const RECORD_INTERVAL: f32 = 0.1;
+// Asteroids further than this from the player are left out of the recording to keep it small
+const RECORD_RADIUS: f32 = 2500.0;
+// Playback holds on the last frame for a moment before looping
+const END_HOLD_SECONDS: f32 = 2.0;
+
+pub struct ReplayPlugin;
+
+impl Plugin for ReplayPlugin {
+    fn build(&self, app: &mut App) {
+        app
+            .insert_resource(ReplayPlayback::default())
+            .add_system_set(
+                SystemSet::on_update(AppState::InGame)
+                    .with_system(number_chasers)
+                    .with_system(record_frame)
+            )
+            .add_system_set(SystemSet::on_enter(AppState::Replay).with_system(enter_replay))
+            .add_system_set(SystemSet::on_update(AppState::Replay).with_system(play_replay.after("update_camera")))
+            .add_system_set(SystemSet::on_exit(AppState::Replay).with_system(exit_replay));
+    }
+}
+
+// Sprites spawned by the replay viewer, despawned when it closes
+#[derive(Component)]
+struct ReplaySprite;
+
+// Stand-in for the earth while a replay plays
+#[derive(Component)]
+struct ReplayPlayer;
+
+// Stand-in for the ship while a replay plays
+#[derive(Component)]
+struct ReplayShip;
+
+// The replay viewer's time readout
+#[derive(Component)]
+struct ReplayText;
+
+#[derive(Default)]
+struct ReplayPlayback {
+    time: f32,
+    // Entities for the recorded asteroids, reused from frame to frame
+    enemy_sprites: Vec<Entity>,
+}
+
+fn ship_angle(rotation: Quat) -> f32 {
+    let facing = rotation * Vec3::Y;
+    facing.y.atan2(facing.x) - std::f32::consts::FRAC_PI_2
+}
+
+fn number_chasers(
+    mut commands: Commands,
+    mut next_id: ResMut<NextChaserId>,
+    chaser_query: Query<Entity, (With<ChasingEnemy>, Without<ChaserId>)>,
+) {
+    for entity in chaser_query.iter() {
+        commands.entity(entity).insert(ChaserId(next_id.0));
+        next_id.0 += 1;
+    }
+}
+
+fn record_frame(
+    time: Res<Time>,
+    game_paused: Res<GamePaused>,
+    player_died: Res<PlayerDied>,
+    score: Res<Score>,
+    mut recording: ResMut<RunRecording>,
+    mut since_last_frame: Local<f32>,
+    player_query: Query<&Transform, With<PlayerOne>>,
+    ship_query: Query<&Transform, (With<PlayerShip>, With<PlayerOne>)>,
+    // Chasers spawned this frame are numbered, and recorded, from the next one
+    chaser_query: Query<(&ChaserId, &Transform, &Sprite, Option<&Chicken>), With<ChasingEnemy>>,
+) {
+    if game_paused.0 || player_died.0 {
+        return;
+    }
+
+    *since_last_frame += time.delta_seconds();
+    if *since_last_frame < RECORD_INTERVAL && !recording.frames.is_empty() {
+        return;
+    }
+    *since_last_frame = 0.0;
+
+    let player_position = player_query.single().translation.truncate();
+
+    let mut enemies: Vec<ReplayEnemy> = chaser_query
+        .iter()
+        .filter(|(_, transform, _, _)| transform.translation.truncate().distance(player_position) < RECORD_RADIUS)
+        .map(|(ChaserId(id), transform, sprite, chicken)| ReplayEnemy {
+            id: *id,
+            position: transform.translation.truncate(),
+            size: sprite.custom_size.map(|size| size.x).unwrap_or(0.0),
+            kind: if chicken.is_some() { ChaserKind::Chicken } else { ChaserKind::Meteor },
+        })
+        .collect();
+    enemies.sort_by_key(|enemy| enemy.id);
+
+    recording.frames.push(ReplayFrame {
+        time: score.survival_time,
+        player_position,
+        ship_rotation: ship_angle(ship_query.single().rotation),
+        enemies,
+    });
+}
+
+// The live game objects are hidden while the replay draws its own copies
+fn set_world_visibility(
+    visible: bool,
+    world_query: &mut Query<&mut Visibility, Or<(With<Player>, With<PlayerShip>, With<ChasingEnemy>)>>,
+) {
+    for mut visibility in world_query.iter_mut() {
+        visibility.is_visible = visible;
+    }
+}
+
+fn enter_replay(
+    mut commands: Commands,
+    font: Res<BoldFont>,
+    mut playback: ResMut<ReplayPlayback>,
+    mut world_query: Query<&mut Visibility, Or<(With<Player>, With<PlayerShip>, With<ChasingEnemy>)>>,
+    player_query: Query<(&Handle<Image>, &Sprite), With<PlayerOne>>,
+    ship_query: Query<(&Handle<TextureAtlas>, &Transform), (With<PlayerShip>, With<PlayerOne>)>,
+) {
+    set_world_visibility(false, &mut world_query);
+    playback.time = 0.0;
+    playback.enemy_sprites.clear();
+
+    let (earth_texture, earth_sprite) = player_query.single();
+    commands
+        .spawn_bundle(SpriteBundle {
+            texture: earth_texture.clone(),
+            sprite: earth_sprite.clone(),
+            ..Default::default()
+        })
+        .insert(ReplaySprite)
+        .insert(ReplayPlayer);
+
+    let (ship_atlas, ship_transform) = ship_query.single();
+    commands
+        .spawn_bundle(SpriteSheetBundle {
+            texture_atlas: ship_atlas.clone(),
+            transform: Transform::from_scale(ship_transform.scale),
+            sprite: TextureAtlasSprite {
+                index: 1,
+                ..Default::default()
+            },
+            ..Default::default()
+        })
+        .insert(ReplaySprite)
+        .insert(ReplayShip);
+
+    commands
+        .spawn_bundle(TextBundle {
+            style: Style {
+                position_type: PositionType::Absolute,
+                position: Rect {
+                    top: Px(16.0),
+                    left: Px(16.0),
+                    ..Default::default()
+                },
+                ..Default::default()
+            },
+            text: Text::with_section(
+                "",
+                TextStyle {
+                    font: font.0.clone(),
+                    font_size: 36.0,
+                    color: Color::WHITE,
+                },
+                Default::default(),
+            ),
+            ..Default::default()
+        })
+        .insert(ReplayText);
+}
+
+fn exit_replay(
+    mut commands: Commands,
+    mut world_query: Query<&mut Visibility, Or<(With<Player>, With<PlayerShip>, With<ChasingEnemy>)>>,
+    replay_query: Query<Entity, Or<(With<ReplaySprite>, With<ReplayText>)>>,
+) {
+    for entity in replay_query.iter() {
+        commands.entity(entity).despawn_recursive();
+    }
+    set_world_visibility(true, &mut world_query);
+}
+
+// Also turns the ghost's ship in ghost races
+pub fn lerp_angle(from: f32, to: f32, t: f32) -> f32 {
+    let mut difference = (to - from) % std::f32::consts::TAU;
+    if difference > std::f32::consts::PI {
+        difference -= std::f32::consts::TAU;
+    } else if difference < -std::f32::consts::PI {
+        difference += std::f32::consts::TAU;
+    }
+    from + difference * t
+}
+
+fn play_replay(
+    mut commands: Commands,
+    time: Res<Time>,
+    mut input: ResMut<Input<KeyCode>>,
+    mut state: ResMut<State<AppState>>,
+    recording: Res<RunRecording>,
+    locale: Res<Locale>,
+    chaser_sprite: Res<ChaserSprite>,
+    chicken_sprite: Res<ChickenSprite>,
+    mut playback: ResMut<ReplayPlayback>,
+    mut camera_query: Query<&mut Transform, (With<Camera2D>, Without<ReplaySprite>)>,
+    mut player_query: Query<&mut Transform, (With<ReplayPlayer>, Without<ReplayShip>, Without<Camera2D>)>,
+    mut ship_query: Query<&mut Transform, (With<ReplayShip>, Without<ReplayPlayer>, Without<Camera2D>)>,
+    mut enemy_query: Query<(&mut Transform, &mut Sprite, &mut Handle<Image>, &mut Visibility), (With<ReplaySprite>, Without<ReplayPlayer>, Without<ReplayShip>, Without<Camera2D>)>,
+    mut text_query: Query<&mut Text, With<ReplayText>>,
+) {
+    if input.just_pressed(KeyCode::Escape) {
+        input.reset(KeyCode::Escape);
+        let _ = state.pop();
+        return;
+    }
+
+    let (first, last) = match (recording.frames.first(), recording.frames.last()) {
+        (Some(first), Some(last)) => (first, last),
+        _ => return,
+    };
+
+    playback.time += time.delta_seconds();
+    let duration = last.time - first.time;
+    if playback.time > duration + END_HOLD_SECONDS {
+        playback.time = 0.0;
+    }
+    let replay_time = first.time + playback.time.min(duration);
+
+    // The frame at or before the playback time, and the one after it to blend towards
+    let next_index = recording.frames.partition_point(|frame| frame.time <= replay_time).min(recording.frames.len() - 1);
+    let index = next_index.saturating_sub(1);
+    let (frame, next_frame) = (&recording.frames[index], &recording.frames[next_index]);
+    let t =
+        if next_frame.time > frame.time {
+            ((replay_time - frame.time) / (next_frame.time - frame.time)).clamp(0.0, 1.0)
+        } else {
+            0.0
+        };
+
+    let player_position = frame.player_position.lerp(next_frame.player_position, t);
+    player_query.single_mut().translation = player_position.extend(0.0);
+    let mut ship_transform = ship_query.single_mut();
+    ship_transform.translation = player_position.extend(0.0);
+    ship_transform.rotation = Quat::from_rotation_z(lerp_angle(frame.ship_rotation, next_frame.ship_rotation, t));
+
+    let mut camera_transform = camera_query.single_mut();
+    camera_transform.translation.x = player_position.x;
+    camera_transform.translation.y = player_position.y;
+    camera_transform.rotation = Quat::IDENTITY;
+
+    for (i, enemy) in frame.enemies.iter().enumerate() {
+        let position = match next_frame.enemies.binary_search_by_key(&enemy.id, |next| next.id) {
+            Ok(next) => enemy.position.lerp(next_frame.enemies[next].position, t),
+            Err(_) => enemy.position,
+        };
+        let texture = match enemy.kind {
+            ChaserKind::Meteor => chaser_sprite.0.clone(),
+            ChaserKind::Chicken => chicken_sprite.0.clone(),
+        };
+
+        match playback.enemy_sprites.get(i).and_then(|entity| enemy_query.get_mut(*entity).ok()) {
+            Some((mut transform, mut sprite, mut image, mut visibility)) => {
+                transform.translation = position.extend(0.0);
+                sprite.custom_size = Some(Vec2::new(enemy.size, enemy.size));
+                if *image != texture {
+                    *image = texture;
+                }
+                visibility.is_visible = true;
+            }
+            None => {
+                let entity = commands
+                    .spawn_bundle(SpriteBundle {
+                        texture,
+                        sprite: Sprite {
+                            custom_size: Some(Vec2::new(enemy.size, enemy.size)),
+                            ..Default::default()
+                        },
+                        transform: Transform::from_translation(position.extend(0.0)),
+                        ..Default::default()
+                    })
+                    .insert(ReplaySprite)
+                    .id();
+                playback.enemy_sprites.push(entity);
+            }
+        }
+    }
+
+    for entity in playback.enemy_sprites.iter().skip(frame.enemies.len()) {
+        if let Ok((_, _, _, mut visibility)) = enemy_query.get_mut(*entity) {
+            visibility.is_visible = false;
+        }
+    }
+
+    text_query.single_mut().sections[0].value = locale.format(
+        "replay.status",
+        &[("time", format!("{:.1}", replay_time)), ("length", format!("{:.1}", last.time))],
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use std::f32::consts::PI;
+
+    use super::*;
+
+    #[test]
+    fn lerp_angle_blends_small_turns_directly() {
+        assert!((lerp_angle(0.0, 1.0, 0.5) - 0.5).abs() < 1e-5);
+        assert!((lerp_angle(1.0, -1.0, 0.25) - 0.5).abs() < 1e-5);
+    }
+
+    #[test]
+    fn lerp_angle_takes_the_short_way_round() {
+        // Halfway between just under +PI and just over -PI is PI, not 0
+        assert!((lerp_angle(PI - 0.1, -PI + 0.1, 0.5) - PI).abs() < 1e-5);
+        assert!((lerp_angle(-PI + 0.1, PI - 0.1, 0.5) + PI).abs() < 1e-5);
+    }
+
+    #[test]
+    fn lerp_angle_ends_facing_the_target() {
+        let angle = lerp_angle(0.5, 0.5 + 3.0 * std::f32::consts::TAU, 1.0);
+        assert!((angle - 0.5).abs() < 1e-4);
+    }
+}
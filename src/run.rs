@@ -0,0 +1,158 @@
+use bevy::prelude::*;
+use heron::prelude::*;
+use rand::SeedableRng;
+use rand_chacha::ChaCha12Rng;
+
+use crate::asteroids::update_enemy_count_text;
+use crate::player::MAX_HEALTH;
+use crate::types::*;
+
+const MAX_CHASERS: u32 = 1000;
+// Seconds between each step up in the size of newly spawned asteroids
+pub const SPAWN_SIZE_INTERVAL: f32 = 5.0;
+
+// Owns the run lifecycle. A run starts with a StartRunEvent and ends when the game leaves InGame,
+// and both ends clear out the RunScoped entities. Per-run resources are all created by insert_run_resources.
+pub struct RunPlugin;
+
+impl Plugin for RunPlugin {
+    fn build(&self, app: &mut App) {
+        app
+            .add_event::<StartRunEvent>()
+            .add_event::<RestartRequestEvent>()
+            .init_resource::<SimStep>()
+            .insert_resource(ReplacedDifficulty(None))
+            .add_startup_system(setup)
+            .add_system_to_stage(CoreStage::PreUpdate, update_sim_step)
+            .add_system_set(SystemSet::on_enter(AppState::InGame).with_system(begin_run))
+            .add_system_set(
+                SystemSet::on_update(AppState::InGame)
+                    .with_system(restart_input)
+                    .with_system(forward_restart_requests.before("start_run"))
+                    .with_system(start_run.label("start_run"))
+            )
+            .add_system_set(SystemSet::on_exit(AppState::InGame).with_system(end_run));
+    }
+}
+
+// Every per-run resource, as a fresh run starts with them. Inserting a resource replaces the old one.
+fn insert_run_resources(commands: &mut Commands, difficulty: Difficulty, seed_override: &SeedOverride) {
+    // The seed is kept so it can be shown on the game over screen
+    let seed = seed_override.0.unwrap_or_else(rand::random);
+    commands.insert_resource(GameSeed(seed));
+    commands.insert_resource(RandomGenerator(ChaCha12Rng::seed_from_u64(seed)));
+
+    commands.insert_resource(SpawnTimer(Timer::from_seconds(difficulty.spawn_interval(), true)));
+    commands.insert_resource(IncreaseSpawnSizeTimer(Timer::from_seconds(SPAWN_SIZE_INTERVAL, true)));
+    commands.insert_resource(SpawnSizeIncrements(0));
+    commands.insert_resource(ChaserCount::new(0, MAX_CHASERS));
+    commands.insert_resource(PlayerDied(false));
+    commands.insert_resource(GamePaused(false));
+
+    commands.insert_resource(Score { points: 0.0, survival_time: 0.0 });
+    commands.insert_resource(ScoreMilestones::default());
+    commands.insert_resource(NearMissChain::default());
+    commands.insert_resource(RunStats::default());
+    // Teleporting back to the start isn't travel
+    commands.insert_resource(LastPlayerPosition(None));
+    commands.insert_resource(RunRecording::default());
+    commands.insert_resource(NextChaserId(0));
+    // Shake and music intensity from the last run shouldn't carry over into this one
+    commands.insert_resource(CameraTrauma(0.0));
+    commands.insert_resource(Tension::default());
+}
+
+fn despawn_run_scoped(commands: &mut Commands, run_scoped_query: &Query<Entity, With<RunScoped>>) {
+    for entity in run_scoped_query.iter() {
+        commands.entity(entity).despawn_recursive();
+    }
+}
+
+fn setup(
+    mut commands: Commands,
+    difficulty: Res<Difficulty>,
+    seed_override: Res<SeedOverride>,
+) {
+    insert_run_resources(&mut commands, *difficulty, &seed_override);
+}
+
+fn begin_run(
+    mut start_run_events: EventWriter<StartRunEvent>,
+) {
+    start_run_events.send(StartRunEvent);
+}
+
+// The online session steps the game itself
+fn update_sim_step(
+    time: Res<Time>,
+    mut sim: ResMut<SimStep>,
+) {
+    if !sim.lockstep {
+        sim.delta = time.delta();
+    }
+}
+
+fn restart_input(
+    input: Res<Input<KeyCode>>,
+    player_died: Res<PlayerDied>,
+    mut restart_requests: EventWriter<RestartRequestEvent>,
+) {
+    if player_died.0 && input.just_pressed(KeyCode::R) {
+        restart_requests.send(RestartRequestEvent);
+    }
+}
+
+// Online, the session sends the StartRunEvent once the restart has gone out with a tick
+fn forward_restart_requests(
+    sim: Res<SimStep>,
+    mut restart_requests: EventReader<RestartRequestEvent>,
+    mut start_run_events: EventWriter<StartRunEvent>,
+) {
+    if restart_requests.iter().count() > 0 && !sim.lockstep {
+        start_run_events.send(StartRunEvent);
+    }
+}
+
+fn start_run(
+    mut commands: Commands,
+    mut start_run_events: EventReader<StartRunEvent>,
+    difficulty: Res<Difficulty>,
+    seed_override: Res<SeedOverride>,
+    mut physics_time: ResMut<PhysicsTime>,
+    run_scoped_query: Query<Entity, With<RunScoped>>,
+    mut player_query: Query<(&mut Transform, &mut Velocity, &mut PlayerHealth, &mut ReviveProgress), With<PlayerOne>>,
+    mut enemy_count_text_query: Query<&mut Text, With<EnemyCountText>>,
+) {
+    if start_run_events.iter().count() > 0 {
+        despawn_run_scoped(&mut commands, &run_scoped_query);
+        insert_run_resources(&mut commands, *difficulty, &seed_override);
+
+        // Player one lives across runs, since so much of the game looks it up. Player two is RunScoped.
+        let (mut transform, mut velocity, mut health, mut revive_progress) = player_query.single_mut();
+        *transform = Transform::from_xyz(0.0, 0.0, 0.0);
+        *velocity = Velocity::from_linear(Vec3::new(0.0, 0.0, 0.0));
+        health.0 = MAX_HEALTH;
+        revive_progress.0 = 0.0;
+
+        update_enemy_count_text(&mut enemy_count_text_query.single_mut(), 0);
+        physics_time.resume();
+    }
+}
+
+// Only runs when InGame is actually left (for the main menu), not when the options or a replay are pushed over it
+fn end_run(
+    mut commands: Commands,
+    mut chaser_count: ResMut<ChaserCount>,
+    mut difficulty: ResMut<Difficulty>,
+    mut replaced_difficulty: ResMut<ReplacedDifficulty>,
+    run_scoped_query: Query<Entity, With<RunScoped>>,
+    mut enemy_count_text_query: Query<&mut Text, With<EnemyCountText>>,
+) {
+    despawn_run_scoped(&mut commands, &run_scoped_query);
+    chaser_count.current = 0;
+    update_enemy_count_text(&mut enemy_count_text_query.single_mut(), 0);
+
+    if let Some(menu_difficulty) = replaced_difficulty.0.take() {
+        *difficulty = menu_difficulty;
+    }
+}
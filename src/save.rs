@@ -0,0 +1,285 @@
+use std::time::Duration;
+
+use bevy::prelude::*;
+use heron::prelude::*;
+use rand::SeedableRng;
+use rand_chacha::ChaCha12Rng;
+use serde::Deserialize;
+
+use crate::asteroids::{spawn_chaser, update_enemy_count_text};
+use crate::persistence;
+use crate::run::SPAWN_SIZE_INTERVAL;
+use crate::types::*;
+
+const SAVED_RUN_FILE: &str = "saved_run.ron";
+// Bump this whenever SavedRun changes shape or meaning
+const SAVE_VERSION: u32 = 3;
+
+pub struct SavePlugin;
+
+impl Plugin for SavePlugin {
+    fn build(&self, app: &mut App) {
+        app
+            .insert_resource(PendingResume(None))
+            .add_system_set(SystemSet::on_update(AppState::MainMenu).with_system(continue_run.after("menu_navigation")))
+            .add_system_set(
+                SystemSet::on_update(AppState::InGame)
+                    .with_system(save_on_quit.after("menu_navigation"))
+                    // Has to overwrite everything the normal run start resets. Commands are applied in system order, so these inserts win.
+                    .with_system(resume_run.after("start_run"))
+            );
+    }
+}
+
+// Set by Continue on the main menu, and applied once the run it starts has been reset
+struct PendingResume(Option<SavedRun>);
+
+// Just enough of a save file to check its version before trying to read the rest
+#[derive(Deserialize)]
+struct SaveHeader {
+    version: u32,
+}
+
+fn is_current_version(header: &SaveHeader) -> bool {
+    header.version == SAVE_VERSION
+}
+
+pub fn load_saved_run() -> Option<SavedRun> {
+    if !persistence::exists(SAVED_RUN_FILE) {
+        return None;
+    }
+
+    let header: Option<SaveHeader> = persistence::load(SAVED_RUN_FILE);
+    let saved_run = match header {
+        Some(header) if !is_current_version(&header) => {
+            warn!("Ignoring saved run from save version {}, expected {}", header.version, SAVE_VERSION);
+            None
+        }
+        Some(_) => persistence::load(SAVED_RUN_FILE),
+        None => None,
+    };
+
+    // It can never be resumed, so there's no point keeping it around
+    if saved_run.is_none() {
+        warn!("Deleting {} since it can't be resumed", SAVED_RUN_FILE);
+        persistence::delete(SAVED_RUN_FILE);
+    }
+    saved_run
+}
+
+// Quitting to the menu from the pause menu keeps the run for later. The pause menu handles the actual quitting.
+fn save_on_quit(
+    mut actions: EventReader<MenuActionEvent>,
+    game_paused: Res<GamePaused>,
+    player_died: Res<PlayerDied>,
+    difficulty: Res<Difficulty>,
+    game_seed: Res<GameSeed>,
+    random_gen: Res<RandomGenerator>,
+    // Grouped to stay under the system parameter limit
+    (spawn_timer, size_timer, size_increments): (Res<SpawnTimer>, Res<IncreaseSpawnSizeTimer>, Res<SpawnSizeIncrements>),
+    chaser_count: Res<ChaserCount>,
+    next_chaser_id: Res<NextChaserId>,
+    (score, chain, milestones): (Res<Score>, Res<NearMissChain>, Res<ScoreMilestones>),
+    stats: Res<RunStats>,
+    recording: Res<RunRecording>,
+    player_query: Query<(&Transform, &Velocity, &PlayerHealth, &Thrusting), With<Player>>,
+    ship_query: Query<&Transform, (With<PlayerShip>, With<PlayerOne>)>,
+    chaser_query: Query<(&Transform, &Velocity, &SizeScale, &Speed, &ChaserId, Option<&Chicken>, Option<&Spotted>), With<ChasingEnemy>>,
+) {
+    let quitting = actions.iter().filter(|MenuActionEvent(action)| *action == MenuAction::QuitToMenu).count() > 0;
+    if !quitting || !game_paused.0 || player_died.0 {
+        return;
+    }
+
+    // Only single player runs are saved. A co-op run just ends when it's quit.
+    let (player_transform, player_velocity, health, Thrusting(thrusting)) = match player_query.get_single() {
+        Ok(player) => player,
+        Err(_) => return,
+    };
+    let ship_transform = ship_query.single();
+
+    let chasers = chaser_query
+        .iter()
+        .map(|(transform, velocity, size_scale, speed, ChaserId(id), chicken, spotted)| SavedChaser {
+            position: transform.translation,
+            rotation: transform.rotation,
+            linear_velocity: velocity.linear,
+            angular_velocity: velocity.angular.into(),
+            size_scale: size_scale.0,
+            speed: speed.0,
+            kind: if chicken.is_some() { ChaserKind::Chicken } else { ChaserKind::Meteor },
+            spotted: spotted.is_some(),
+            id: *id,
+        })
+        .collect();
+
+    let saved_run = SavedRun {
+        version: SAVE_VERSION,
+        difficulty: *difficulty,
+        seed: game_seed.0,
+        rng_seed: random_gen.0.get_seed(),
+        rng_word_pos: random_gen.0.get_word_pos() as u64,
+        player_position: player_transform.translation,
+        player_velocity: player_velocity.linear,
+        health: health.0,
+        thrusting: *thrusting,
+        ship_position: ship_transform.translation,
+        ship_rotation: ship_transform.rotation,
+        chasers,
+        next_chaser_id: next_chaser_id.0,
+        chaser_max: chaser_count.max,
+        spawn_interval: spawn_timer.0.duration().as_secs_f32(),
+        spawn_elapsed: spawn_timer.0.elapsed_secs(),
+        size_increase_elapsed: size_timer.0.elapsed_secs(),
+        size_increments: size_increments.0,
+        points: score.points,
+        survival_time: score.survival_time,
+        near_miss_chain: chain.count,
+        stats: stats.clone(),
+        milestones: milestones.clone(),
+        recording: recording.clone(),
+    };
+
+    persistence::save(SAVED_RUN_FILE, &saved_run);
+}
+
+fn continue_run(
+    mut actions: EventReader<MenuActionEvent>,
+    mut state: ResMut<State<AppState>>,
+    mut difficulty: ResMut<Difficulty>,
+    mut replaced_difficulty: ResMut<ReplacedDifficulty>,
+    mut pending: ResMut<PendingResume>,
+    mut player_count: ResMut<PlayerCount>,
+) {
+    for MenuActionEvent(action) in actions.iter() {
+        if *action != MenuAction::Continue {
+            continue;
+        }
+
+        if let Some(saved_run) = load_saved_run() {
+            // Set first so the run starts with the saved difficulty's spawn rate and speed, until the run ends
+            replaced_difficulty.0.get_or_insert(*difficulty);
+            *difficulty = saved_run.difficulty;
+            pending.0 = Some(saved_run);
+            player_count.0 = 1;
+            let _ = state.set(AppState::InGame);
+        }
+    }
+}
+
+fn timer_at(seconds: f32, elapsed: f32, paused: bool) -> Timer {
+    let mut timer = Timer::from_seconds(seconds, true);
+    timer.set_elapsed(Duration::from_secs_f32(elapsed));
+    if paused {
+        timer.pause();
+    }
+    timer
+}
+
+// Runs in the same frame as the StartRunEvent that entering InGame sends, after the fresh run has been set up
+fn resume_run(
+    mut commands: Commands,
+    mut events: EventReader<StartRunEvent>,
+    mut pending: ResMut<PendingResume>,
+    mut physics_time: ResMut<PhysicsTime>,
+    chaser_sprite: Res<ChaserSprite>,
+    chicken_sprite: Res<ChickenSprite>,
+    mut player_query: Query<(&mut Transform, &mut Velocity, &mut PlayerHealth, &mut Thrusting), (With<PlayerOne>, Without<PlayerShip>)>,
+    mut ship_query: Query<&mut Transform, (With<PlayerShip>, With<PlayerOne>, Without<Player>)>,
+    mut enemy_count_text_query: Query<&mut Text, With<EnemyCountText>>,
+) {
+    if events.iter().count() == 0 {
+        return;
+    }
+
+    let saved_run = match pending.0.take() {
+        Some(saved_run) => saved_run,
+        None => return,
+    };
+
+    let mut rng = ChaCha12Rng::from_seed(saved_run.rng_seed);
+    rng.set_word_pos(saved_run.rng_word_pos as u128);
+    commands.insert_resource(GameSeed(saved_run.seed));
+    commands.insert_resource(RandomGenerator(rng));
+
+    // Comes back paused, so the player has a moment to get their bearings
+    commands.insert_resource(GamePaused(true));
+    commands.insert_resource(SpawnTimer(timer_at(saved_run.spawn_interval, saved_run.spawn_elapsed, true)));
+    commands.insert_resource(IncreaseSpawnSizeTimer(timer_at(SPAWN_SIZE_INTERVAL, saved_run.size_increase_elapsed, false)));
+    commands.insert_resource(SpawnSizeIncrements(saved_run.size_increments));
+    commands.insert_resource(ChaserCount::new(saved_run.chasers.len() as u32, saved_run.chaser_max));
+    commands.insert_resource(NextChaserId(saved_run.next_chaser_id));
+    physics_time.pause();
+
+    let (mut transform, mut velocity, mut health, mut thrusting) = player_query.single_mut();
+    transform.translation = saved_run.player_position;
+    *velocity = Velocity::from_linear(saved_run.player_velocity);
+    health.0 = saved_run.health;
+    thrusting.0 = saved_run.thrusting;
+
+    let mut ship_transform = ship_query.single_mut();
+    ship_transform.translation = saved_run.ship_position;
+    ship_transform.rotation = saved_run.ship_rotation;
+
+    for chaser in saved_run.chasers.iter() {
+        let texture = match chaser.kind {
+            ChaserKind::Meteor => chaser_sprite.0.clone(),
+            ChaserKind::Chicken => chicken_sprite.0.clone(),
+        };
+        let entity = spawn_chaser(&mut commands, texture, chaser.position.truncate(), chaser.size_scale, chaser.speed);
+        commands
+            .entity(entity)
+            .insert(Transform {
+                translation: chaser.position,
+                rotation: chaser.rotation,
+                ..Default::default()
+            })
+            .insert(Velocity::from_linear(chaser.linear_velocity).with_angular(chaser.angular_velocity.into()))
+            .insert(ChaserId(chaser.id))
+            // Its spawn is already in the saved stats
+            .insert(Resumed);
+        if chaser.kind == ChaserKind::Chicken {
+            commands.entity(entity).insert(Chicken);
+        }
+        // Already counted in the saved stats
+        if chaser.spotted {
+            commands.entity(entity).insert(Spotted);
+        }
+    }
+    update_enemy_count_text(&mut enemy_count_text_query.single_mut(), saved_run.chasers.len() as u32);
+
+    commands.insert_resource(Score {
+        points: saved_run.points,
+        survival_time: saved_run.survival_time,
+    });
+    commands.insert_resource(NearMissChain { count: saved_run.near_miss_chain });
+    commands.insert_resource(saved_run.stats);
+    commands.insert_resource(saved_run.milestones);
+    commands.insert_resource(saved_run.recording);
+
+    // The save is used up once the run is back, so it can't be resumed twice
+    persistence::delete(SAVED_RUN_FILE);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn header(contents: &str) -> SaveHeader {
+        ron::from_str(contents).unwrap()
+    }
+
+    #[test]
+    fn version_check_accepts_only_the_current_version() {
+        assert!(is_current_version(&header(&format!("(version: {})", SAVE_VERSION))));
+        assert!(!is_current_version(&header(&format!("(version: {})", SAVE_VERSION - 1))));
+        assert!(!is_current_version(&header(&format!("(version: {})", SAVE_VERSION + 1))));
+    }
+
+    #[test]
+    fn header_is_read_from_a_whole_save() {
+        // The rest of an old save might not parse as a SavedRun any more, so the header can't depend on it
+        let old_save = "(version: 1, difficulty: Hard, seed: 7, chasers: [(position: (1.0, 2.0, 0.0))])";
+        assert_eq!(header(old_save).version, 1);
+    }
+}
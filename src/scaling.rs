@@ -0,0 +1,142 @@
+use bevy::prelude::*;
+use bevy::window::WindowResized;
+
+use crate::menu::{BUTTON_HEIGHT, BUTTON_WIDTH};
+use crate::types::*;
+
+// The area of the world that is always visible at zoom 1.0, whatever the window size.
+// Windows with a different aspect ratio see a bit more along one axis.
+pub const VIEW_WIDTH: f32 = 1200.0;
+pub const VIEW_HEIGHT: f32 = 800.0;
+
+// Keeps the HUD readable in tiny windows and from getting silly on huge screens
+const MIN_HUD_SCALE: f32 = 0.6;
+const MAX_HUD_SCALE: f32 = 2.0;
+
+// The hearts image is drawn at this size at the reference resolution
+const HEART_SIZE: f32 = 48.0;
+
+pub struct ScalingPlugin;
+
+impl Plugin for ScalingPlugin {
+    fn build(&self, app: &mut App) {
+        app
+            .add_startup_system(setup)
+            .add_system(update_view_fit.label("update_view_fit"))
+            .add_system(scale_hud_text.after("update_view_fit"))
+            .add_system(scale_hearts.after("update_view_fit"))
+            .add_system(scale_menu_buttons.after("update_view_fit"));
+    }
+}
+
+// The font sizes a HUD text was created with, so scaling never compounds
+#[derive(Component)]
+struct BaseFontSizes(Vec<f32>);
+
+fn view_fit(width: f32, height: f32) -> ViewFit {
+    // Whichever axis is tighter decides the fit, so the whole logical view always shows
+    let world_per_pixel = (VIEW_WIDTH / width).max(VIEW_HEIGHT / height);
+
+    ViewFit {
+        world_per_pixel,
+        hud_scale: (1.0 / world_per_pixel).clamp(MIN_HUD_SCALE, MAX_HUD_SCALE),
+    }
+}
+
+fn setup(
+    mut commands: Commands,
+    windows: Res<Windows>,
+) {
+    let window = windows.get_primary().unwrap();
+    commands.insert_resource(view_fit(window.width(), window.height()));
+}
+
+fn update_view_fit(
+    mut resize_events: EventReader<WindowResized>,
+    mut fit: ResMut<ViewFit>,
+) {
+    // Minimising shows up as a resize to zero, which would divide by zero here
+    if let Some(event) = resize_events.iter().rfind(|event| event.width > 0. && event.height > 0.) {
+        *fit = view_fit(event.width, event.height);
+    }
+}
+
+// Only UI text is scaled. World space text is already scaled by the camera.
+fn scale_hud_text(
+    mut commands: Commands,
+    fit: Res<ViewFit>,
+    mut new_text_query: Query<(Entity, &mut Text), (With<Node>, Without<BaseFontSizes>)>,
+    mut text_query: Query<(&mut Text, &BaseFontSizes)>,
+) {
+    for (entity, mut text) in new_text_query.iter_mut() {
+        let base_sizes: Vec<f32> = text.sections.iter().map(|section| section.style.font_size).collect();
+        for (section, base_size) in text.sections.iter_mut().zip(base_sizes.iter()) {
+            section.style.font_size = base_size * fit.hud_scale;
+        }
+        commands.entity(entity).insert(BaseFontSizes(base_sizes));
+    }
+
+    if fit.is_changed() {
+        for (mut text, BaseFontSizes(base_sizes)) in text_query.iter_mut() {
+            for (section, base_size) in text.sections.iter_mut().zip(base_sizes.iter()) {
+                section.style.font_size = base_size * fit.hud_scale;
+            }
+        }
+    }
+}
+
+// Menu screens come and go, so these check every node rather than only reacting to resizes
+fn scale_hearts(
+    fit: Res<ViewFit>,
+    mut heart_query: Query<&mut Style, With<HeartSprite>>,
+) {
+    let size = Size::new(Val::Px(HEART_SIZE * fit.hud_scale), Val::Px(HEART_SIZE * fit.hud_scale));
+    for mut style in heart_query.iter_mut() {
+        if style.size != size {
+            style.size = size;
+        }
+    }
+}
+
+fn scale_menu_buttons(
+    fit: Res<ViewFit>,
+    mut button_query: Query<&mut Style, With<MenuButton>>,
+) {
+    let size = Size::new(Val::Px(BUTTON_WIDTH * fit.hud_scale), Val::Px(BUTTON_HEIGHT * fit.hud_scale));
+    for mut style in button_query.iter_mut() {
+        if style.size != size {
+            style.size = size;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn view_fit_matches_the_reference_window() {
+        let fit = view_fit(VIEW_WIDTH, VIEW_HEIGHT);
+        assert!((fit.world_per_pixel - 1.0).abs() < 1e-5);
+        assert!((fit.hud_scale - 1.0).abs() < 1e-5);
+    }
+
+    #[test]
+    fn view_fit_always_shows_the_whole_view() {
+        for (width, height) in [(2400.0, 800.0), (600.0, 1000.0), (1920.0, 1080.0), (333.0, 777.0)] {
+            let fit = view_fit(width, height);
+            assert!(width * fit.world_per_pixel >= VIEW_WIDTH - 1e-3);
+            assert!(height * fit.world_per_pixel >= VIEW_HEIGHT - 1e-3);
+            // And the tighter axis fits exactly, so nothing extra is shown along both
+            let fits_width = (width * fit.world_per_pixel - VIEW_WIDTH).abs() < 1e-3;
+            let fits_height = (height * fit.world_per_pixel - VIEW_HEIGHT).abs() < 1e-3;
+            assert!(fits_width || fits_height);
+        }
+    }
+
+    #[test]
+    fn view_fit_clamps_the_hud_scale() {
+        assert!((view_fit(300.0, 200.0).hud_scale - MIN_HUD_SCALE).abs() < 1e-5);
+        assert!((view_fit(4800.0, 3200.0).hud_scale - MAX_HUD_SCALE).abs() < 1e-5);
+    }
+}
@@ -0,0 +1,278 @@
+use bevy::prelude::*;
+use bevy::ui::Val::Px;
+use heron::prelude::*;
+
+use crate::floating_text::spawn_floating_text;
+use crate::persistence;
+use crate::utilities::*;
+use crate::types::*;
+
+const HIGH_SCORES_FILE: &str = "high_scores.ron";
+const MAX_HIGH_SCORES: usize = 10;
+const POINTS_PER_SECOND: f32 = 10.0;
+// Near miss bonus for a normal sized asteroid passing at the reference speed, before any multipliers
+const NEAR_MISS_POINTS: f32 = 25.0;
+const NEAR_MISS_REFERENCE_SPEED: f32 = 300.0;
+// How far past touching distance a chaser can be and still count as a near miss
+const NEAR_MISS_MARGIN: f32 = 30.0;
+const CHAIN_COLOR: Color = Color::rgb(1.0, 0.8, 0.2);
+
+pub struct ScorePlugin;
+
+impl Plugin for ScorePlugin {
+    fn build(&self, app: &mut App) {
+        app
+            .add_event::<NearMissEvent>()
+            .insert_resource(persistence::load::<HighScores>(HIGH_SCORES_FILE).unwrap_or_default())
+            .add_startup_system_to_stage(StartupStage::PostStartup, setup)
+            .add_system(update_score_text)
+            .add_system(update_chain_text)
+            .add_system_set(
+                SystemSet::on_update(AppState::InGame)
+                    .with_system(accumulate_score)
+                    .with_system(break_chain_on_damage.label("break_chain_on_damage"))
+                    .with_system(detect_near_misses.label("detect_near_misses"))
+                    .with_system(award_near_miss_bonus.after("break_chain_on_damage").after("detect_near_misses"))
+                    .with_system(record_high_score)
+            );
+    }
+}
+
+// Attached to a chaser while it is inside the near miss band around the player
+#[derive(Component)]
+struct NearMissTracker {
+    peak_relative_speed: f32,
+    touched: bool,
+}
+
+// The HUD text showing the score and near miss chain
+#[derive(Component)]
+struct ScoreText;
+
+// After the main setup, so BoldFont is there to use
+fn setup(
+    mut commands: Commands,
+    font: Res<BoldFont>,
+) {
+    commands
+        .spawn_bundle(NodeBundle {
+            style: Style {
+                position_type: PositionType::Absolute,
+                position: Rect {
+                    bottom: Px(16.0),
+                    right: Px(16.0),
+                    ..Default::default()
+                },
+                padding: Rect::all(Px(8.0)),
+                ..Default::default()
+            },
+            color: Color::NONE.into(),
+            ..Default::default()
+        })
+        .with_children(|parent| {
+            parent
+                .spawn_bundle(TextBundle {
+                    text: Text {
+                        sections: vec![
+                            TextSection {
+                                value: "Score: ".to_string(),
+                                style: TextStyle {
+                                    font: font.0.clone(),
+                                    font_size: 48.0,
+                                    color: Color::WHITE,
+                                },
+                            },
+                            TextSection {
+                                value: "0".to_string(),
+                                style: TextStyle {
+                                    font: font.0.clone(),
+                                    font_size: 48.0,
+                                    color: Color::WHITE,
+                                },
+                            },
+                            // Near miss multiplier, empty until a chain gets going
+                            TextSection {
+                                value: "".to_string(),
+                                style: TextStyle {
+                                    font: font.0.clone(),
+                                    font_size: 36.0,
+                                    color: CHAIN_COLOR,
+                                },
+                            },
+                        ],
+                        ..Default::default()
+                    },
+                    ..Default::default()
+                })
+                .insert(ScoreText)
+                .insert(LocalizedText::new("hud.score"));
+        });
+}
+
+fn accumulate_score(
+    sim: Res<SimStep>,
+    game_paused: Res<GamePaused>,
+    player_died: Res<PlayerDied>,
+    difficulty: Res<Difficulty>,
+    mut score: ResMut<Score>,
+) {
+    if !game_paused.0 && !player_died.0 {
+        score.survival_time += sim.delta_seconds();
+        score.points += POINTS_PER_SECOND * difficulty.score_multiplier() * sim.delta_seconds();
+    }
+}
+
+fn break_chain_on_damage(
+    mut events: EventReader<CollisionEvent>,
+    mut chain: ResMut<NearMissChain>,
+) {
+    let hit = events
+        .iter()
+        .filter(|event| event.is_started())
+        .filter(|event| {
+            let (layers_1, layers_2) = event.collision_layers();
+            (is_player(layers_1) && is_enemy(layers_2)) || (is_player(layers_2) && is_enemy(layers_1))
+        })
+        .count() > 0;
+
+    if hit && chain.count > 0 {
+        chain.count = 0;
+    }
+}
+
+// A chaser entering the band just outside the player's collision shape starts being tracked.
+// If it leaves the band again without ever touching the player, that counts as a near miss.
+fn detect_near_misses(
+    mut commands: Commands,
+    game_paused: Res<GamePaused>,
+    player_died: Res<PlayerDied>,
+    mut near_miss_events: EventWriter<NearMissEvent>,
+    player_query: Query<(&Transform, &Velocity, &CollisionShape, &PlayerHealth), (With<Player>, Without<ChasingEnemy>)>,
+    mut chaser_query: Query<(Entity, &Transform, &Velocity, &CollisionShape, &SizeScale, Option<&mut NearMissTracker>), With<ChasingEnemy>>,
+) {
+    if game_paused.0 || player_died.0 {
+        return;
+    }
+
+    for (entity, transform, velocity, shape, SizeScale(size_scale), tracker) in chaser_query.iter_mut() {
+        // Measured against the closest living player, the one the asteroid is chasing
+        let closest = player_query
+            .iter()
+            .filter(|(_, _, _, health)| health.0 > 0)
+            .min_by(|(a, _, _, _), (b, _, _, _)| {
+                a.translation.distance_squared(transform.translation)
+                    .partial_cmp(&b.translation.distance_squared(transform.translation))
+                    .unwrap_or(std::cmp::Ordering::Equal)
+            });
+        let (player_transform, player_velocity, player_shape, _) = match closest {
+            Some(player) => player,
+            None => return,
+        };
+        let player_radius = sphere_radius(player_shape);
+
+        let touching_distance = player_radius + sphere_radius(shape);
+        let distance = transform.translation.truncate().distance(player_transform.translation.truncate());
+        let relative_speed = (velocity.linear - player_velocity.linear).length();
+
+        match tracker {
+            Some(mut tracker) => {
+                if distance <= touching_distance + 1.0 {
+                    tracker.touched = true;
+                } else if distance > touching_distance + NEAR_MISS_MARGIN {
+                    if !tracker.touched {
+                        near_miss_events.send(NearMissEvent {
+                            enemy: entity,
+                            position: transform.translation,
+                            relative_speed: tracker.peak_relative_speed,
+                            size_scale: *size_scale,
+                        });
+                    }
+                    commands.entity(entity).remove::<NearMissTracker>();
+                } else {
+                    tracker.peak_relative_speed = tracker.peak_relative_speed.max(relative_speed);
+                }
+            }
+            None => {
+                if distance <= touching_distance + NEAR_MISS_MARGIN {
+                    commands.entity(entity).insert(NearMissTracker {
+                        peak_relative_speed: relative_speed,
+                        touched: distance <= touching_distance + 1.0,
+                    });
+                }
+            }
+        }
+    }
+}
+
+// Faster and bigger asteroids are worth more, and every near miss in a chain raises the multiplier for the next
+fn award_near_miss_bonus(
+    mut commands: Commands,
+    mut events: EventReader<NearMissEvent>,
+    font: Res<BoldFont>,
+    locale: Res<Locale>,
+    player_died: Res<PlayerDied>,
+    difficulty: Res<Difficulty>,
+    mut score: ResMut<Score>,
+    mut chain: ResMut<NearMissChain>,
+) {
+    for event in events.iter() {
+        if player_died.0 {
+            continue;
+        }
+
+        chain.count += 1;
+        let speed_factor = (event.relative_speed / NEAR_MISS_REFERENCE_SPEED).clamp(0.5, 3.0);
+        let bonus = NEAR_MISS_POINTS * speed_factor * event.size_scale * chain.multiplier() * difficulty.score_multiplier();
+        score.points += bonus;
+
+        let label =
+            if chain.count > 1 {
+                locale.format("score.near_miss_chain", &[("points", format!("{}", bonus as u32)), ("multiplier", format!("{}", chain.multiplier()))])
+            } else {
+                locale.format("score.near_miss", &[("points", format!("{}", bonus as u32))])
+            };
+        spawn_floating_text(&mut commands, &font.0, event.position, label, CHAIN_COLOR);
+    }
+}
+
+fn record_high_score(
+    player_died: Res<PlayerDied>,
+    score: Res<Score>,
+    difficulty: Res<Difficulty>,
+    mut high_scores: ResMut<HighScores>,
+) {
+    if player_died.is_changed() && player_died.0 {
+        high_scores.0.push(HighScoreEntry {
+            score: score.points as u32,
+            survival_time: score.survival_time,
+            difficulty: *difficulty,
+        });
+        high_scores.0.sort_by_key(|entry| std::cmp::Reverse(entry.score));
+        high_scores.0.truncate(MAX_HIGH_SCORES);
+
+        persistence::save(HIGH_SCORES_FILE, &*high_scores);
+    }
+}
+
+fn update_score_text(
+    score: Res<Score>,
+    mut score_text_query: Query<&mut Text, With<ScoreText>>,
+) {
+    if score.is_changed() {
+        score_text_query.single_mut().sections[1].value = format!("{}", score.points as u32);
+    }
+}
+
+fn update_chain_text(
+    chain: Res<NearMissChain>,
+    mut score_text_query: Query<&mut Text, With<ScoreText>>,
+) {
+    if chain.is_changed() {
+        score_text_query.single_mut().sections[2].value =
+            if chain.count > 1 {
+                format!(" x{}", chain.multiplier())
+            } else {
+                String::new()
+            };
+    }
+}
@@ -0,0 +1,188 @@
+use std::collections::hash_map::Entry;
+use std::collections::HashMap;
+
+use bevy::prelude::*;
+use rand::{Rng, SeedableRng};
+
+use crate::types::*;
+
+// Side length of a square chunk of stars, in layer units
+const CHUNK_SIZE: f32 = 512.0;
+
+struct StarLayerConfig {
+    // How far the layer moves relative to the camera. 0.0 would be painted on the screen, 1.0 moves with the world.
+    parallax: f32,
+    stars_per_chunk: u32,
+    min_size: f32,
+    max_size: f32,
+    brightness: f32,
+}
+
+// Back to front
+const LAYERS: [StarLayerConfig; 3] = [
+    StarLayerConfig { parallax: 0.1, stars_per_chunk: 40, min_size: 1.0, max_size: 2.0, brightness: 0.45 },
+    StarLayerConfig { parallax: 0.3, stars_per_chunk: 20, min_size: 1.5, max_size: 3.0, brightness: 0.65 },
+    StarLayerConfig { parallax: 0.6, stars_per_chunk: 8, min_size: 2.5, max_size: 4.0, brightness: 0.9 },
+];
+
+pub struct StarfieldPlugin;
+
+impl Plugin for StarfieldPlugin {
+    fn build(&self, app: &mut App) {
+        app
+            .insert_resource(LoadedStarChunks::default())
+            // RandomGenerator is inserted by the run plugin's startup system, so the seed has to be taken after that has run
+            .add_startup_system_to_stage(StartupStage::PostStartup, setup)
+            .add_system(update_starfield.after("update_camera"));
+    }
+}
+
+// Seed for the background stars, so chunks look the same when the player comes back to them
+struct StarfieldSeed(u64);
+
+// The usize is the index of the layer's config in LAYERS
+#[derive(Component)]
+struct StarLayer(usize);
+
+// A square of background stars, spawned and despawned as the camera moves
+#[derive(Component)]
+struct StarChunk;
+
+#[derive(Default)]
+struct LoadedStarChunks(HashMap<(usize, IVec2), Entity>);
+
+fn setup(
+    mut commands: Commands,
+    mut random_gen: ResMut<RandomGenerator>,
+) {
+    commands.insert_resource(StarfieldSeed(random_gen.0.gen()));
+
+    for (index, _) in LAYERS.iter().enumerate() {
+        commands
+            .spawn_bundle((
+                // Everything is drawn at z = 0.0 and the 2D camera can only see down to z = -0.1, so the layers sit just behind
+                Transform::from_xyz(0.0, 0.0, -0.09 + index as f32 * 0.01),
+                GlobalTransform::default(),
+            ))
+            .insert(StarLayer(index));
+    }
+}
+
+// Mixes the seed with the chunk's identity so every chunk always regenerates the same stars
+fn chunk_seed(seed: u64, layer: usize, coord: IVec2) -> u64 {
+    // The seed goes through the mixing too, otherwise seed 0 on layer 1 would match seed 1 on layer 0
+    let mut hash = 0x9E37_79B9_7F4A_7C15;
+    for value in [seed, layer as u64, coord.x as u32 as u64, coord.y as u32 as u64] {
+        hash = (hash ^ value).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        hash ^= hash >> 31;
+    }
+    hash
+}
+
+fn spawn_chunk(commands: &mut Commands, seed: u64, layer: usize, coord: IVec2) -> Entity {
+    let config = &LAYERS[layer];
+    let mut rng = rand::rngs::StdRng::seed_from_u64(chunk_seed(seed, layer, coord));
+
+    commands
+        .spawn_bundle((
+            Transform::from_xyz(coord.x as f32 * CHUNK_SIZE, coord.y as f32 * CHUNK_SIZE, 0.0),
+            GlobalTransform::default(),
+        ))
+        .insert(StarChunk)
+        .with_children(|parent| {
+            for _ in 0..config.stars_per_chunk {
+                let size = rng.gen_range(config.min_size..config.max_size);
+                let brightness = config.brightness * rng.gen_range(0.6..1.0);
+                // A slight blue or yellow tint so the field isn't uniformly grey
+                let tint: f32 = rng.gen_range(-0.1..0.1);
+
+                parent.spawn_bundle(SpriteBundle {
+                    sprite: Sprite {
+                        color: Color::rgba(1.0 - tint.max(0.0), 1.0 - tint.abs() * 0.5, 1.0 + tint.min(0.0), brightness),
+                        custom_size: Some(Vec2::new(size, size)),
+                        ..Default::default()
+                    },
+                    transform: Transform::from_xyz(rng.gen_range(0.0..CHUNK_SIZE), rng.gen_range(0.0..CHUNK_SIZE), 0.0),
+                    ..Default::default()
+                });
+            }
+        })
+        .id()
+}
+
+fn update_starfield(
+    mut commands: Commands,
+    seed: Res<StarfieldSeed>,
+    windows: Res<Windows>,
+    mut loaded_chunks: ResMut<LoadedStarChunks>,
+    camera_query: Query<(&Transform, &OrthographicProjection), (With<Camera2D>, Without<StarLayer>)>,
+    mut layer_query: Query<(Entity, &mut Transform, &StarLayer), Without<Camera2D>>,
+) {
+    let (camera_transform, projection) = camera_query.single();
+    let window = windows.get_primary().unwrap();
+    // Extra chunk of margin on every side so stars never pop in on screen, even while the camera is shaking
+    let half_extents = Vec2::new(window.width(), window.height()) * projection.scale / 2. + Vec2::splat(CHUNK_SIZE);
+    let camera_position = camera_transform.translation.truncate();
+
+    for (layer_entity, mut layer_transform, StarLayer(layer)) in layer_query.iter_mut() {
+        let parallax = LAYERS[*layer].parallax;
+
+        // Offsetting the whole layer makes it appear to move at `parallax` times the camera's speed
+        let offset = camera_position * (1.0 - parallax);
+        layer_transform.translation.x = offset.x;
+        layer_transform.translation.y = offset.y;
+
+        let center = camera_position * parallax;
+        let min = ((center - half_extents) / CHUNK_SIZE).floor().as_ivec2();
+        let max = ((center + half_extents) / CHUNK_SIZE).floor().as_ivec2();
+
+        let mut new_chunks = Vec::new();
+        for x in min.x..=max.x {
+            for y in min.y..=max.y {
+                let coord = IVec2::new(x, y);
+                if let Entry::Vacant(entry) = loaded_chunks.0.entry((*layer, coord)) {
+                    let chunk = spawn_chunk(&mut commands, seed.0, *layer, coord);
+                    entry.insert(chunk);
+                    new_chunks.push(chunk);
+                }
+            }
+        }
+        commands.entity(layer_entity).push_children(&new_chunks);
+
+        loaded_chunks.0.retain(|(chunk_layer, coord), chunk| {
+            let in_range = *chunk_layer != *layer
+                || (coord.x >= min.x && coord.x <= max.x && coord.y >= min.y && coord.y <= max.y);
+            if !in_range {
+                commands.entity(*chunk).despawn_recursive();
+            }
+            in_range
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashSet;
+
+    use super::*;
+
+    #[test]
+    fn chunk_seed_is_stable() {
+        let coord = IVec2::new(-3, 7);
+        assert_eq!(chunk_seed(42, 1, coord), chunk_seed(42, 1, coord));
+    }
+
+    #[test]
+    fn chunk_seed_differs_between_chunks_layers_and_seeds() {
+        let mut seeds = HashSet::new();
+        for seed in [0, 1, u64::MAX] {
+            for layer in 0..LAYERS.len() {
+                for x in -4..=4 {
+                    for y in -4..=4 {
+                        assert!(seeds.insert(chunk_seed(seed, layer, IVec2::new(x, y))));
+                    }
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,209 @@
+use bevy::prelude::*;
+use heron::prelude::*;
+
+use crate::persistence;
+use crate::utilities::*;
+use crate::types::*;
+
+const LAST_RUN_FILE: &str = "last_run.ron";
+
+// SizeScale cut-offs for the spawn counts. Most asteroids spawn between 0.8 and 1.2.
+const SMALL_SIZE_SCALE: f32 = 0.9;
+const LARGE_SIZE_SCALE: f32 = 1.5;
+
+pub struct StatsPlugin;
+
+impl Plugin for StatsPlugin {
+    fn build(&self, app: &mut App) {
+        app
+            .add_system_set(
+                SystemSet::on_update(AppState::InGame)
+                    .with_system(track_distance)
+                    .with_system(track_hits)
+                    .with_system(track_near_misses)
+                    .with_system(track_peak_enemies)
+                    .with_system(track_chickens)
+                    .with_system(track_speed)
+                    .with_system(track_thrusting.after("player_movement"))
+                    .with_system(track_spawns)
+                    .with_system(track_health_time)
+                    .with_system(export_stats)
+            );
+    }
+}
+
+fn track_distance(
+    game_paused: Res<GamePaused>,
+    player_died: Res<PlayerDied>,
+    mut stats: ResMut<RunStats>,
+    mut last_position: ResMut<LastPlayerPosition>,
+    player_query: Query<&Transform, With<PlayerOne>>,
+) {
+    if game_paused.0 || player_died.0 {
+        return;
+    }
+
+    let position = player_query.single().translation.truncate();
+    if let Some(last) = last_position.0 {
+        stats.distance += position.distance(last);
+    }
+    last_position.0 = Some(position);
+}
+
+fn track_hits(
+    mut events: EventReader<CollisionEvent>,
+    player_died: Res<PlayerDied>,
+    mut stats: ResMut<RunStats>,
+) {
+    for event in events.iter() {
+        if event.is_started() && !player_died.0 {
+            let (layers_1, layers_2) = event.collision_layers();
+            if (is_player(layers_1) && is_enemy(layers_2)) || (is_player(layers_2) && is_enemy(layers_1)) {
+                stats.hits_taken += 1;
+            }
+        }
+    }
+}
+
+fn track_near_misses(
+    mut events: EventReader<NearMissEvent>,
+    mut stats: ResMut<RunStats>,
+) {
+    stats.near_misses += events.iter().count() as u32;
+}
+
+// Half the width and height of what the camera sees, in world units
+fn half_view(windows: &Windows, projection: &OrthographicProjection) -> Vec2 {
+    let window = windows.get_primary().unwrap();
+    Vec2::new(window.width(), window.height()) * projection.scale / 2.
+}
+
+fn track_peak_enemies(
+    windows: Res<Windows>,
+    chaser_count: Res<ChaserCount>,
+    mut stats: ResMut<RunStats>,
+    camera_query: Query<(&Transform, &OrthographicProjection), With<Camera2D>>,
+    chaser_query: Query<&Transform, (With<ChasingEnemy>, Without<Camera2D>)>,
+) {
+    if chaser_count.current > stats.peak_enemies {
+        stats.peak_enemies = chaser_count.current;
+    }
+
+    let (camera_transform, projection) = camera_query.single();
+    let half_view = half_view(&windows, projection);
+    let camera_position = camera_transform.translation.truncate();
+    let on_screen = chaser_query
+        .iter()
+        .filter(|transform| {
+            let offset = (transform.translation.truncate() - camera_position).abs();
+            offset.x <= half_view.x && offset.y <= half_view.y
+        })
+        .count() as u32;
+    stats.peak_on_screen = stats.peak_on_screen.max(on_screen);
+}
+
+// A chicken counts as spotted once any part of it has been inside the camera's view
+fn track_chickens(
+    mut commands: Commands,
+    windows: Res<Windows>,
+    player_died: Res<PlayerDied>,
+    mut stats: ResMut<RunStats>,
+    camera_query: Query<(&Transform, &OrthographicProjection), With<Camera2D>>,
+    chicken_query: Query<(Entity, &Transform, &Sprite), (With<Chicken>, Without<Spotted>, Without<Camera2D>)>,
+) {
+    if player_died.0 {
+        return;
+    }
+
+    let (camera_transform, projection) = camera_query.single();
+    let half_view = half_view(&windows, projection);
+    let camera_position = camera_transform.translation.truncate();
+
+    for (entity, transform, sprite) in chicken_query.iter() {
+        let half_size = sprite.custom_size.unwrap_or(Vec2::ZERO) / 2.;
+        let offset = (transform.translation.truncate() - camera_position).abs();
+
+        if offset.x <= half_view.x + half_size.x && offset.y <= half_view.y + half_size.y {
+            commands.entity(entity).insert(Spotted);
+            stats.chickens_spotted += 1;
+        }
+    }
+}
+
+fn track_speed(
+    player_died: Res<PlayerDied>,
+    mut stats: ResMut<RunStats>,
+    player_query: Query<&Velocity, With<Player>>,
+) {
+    if player_died.0 {
+        return;
+    }
+
+    for velocity in player_query.iter() {
+        let speed = velocity.linear.truncate().length();
+        if speed > stats.top_speed {
+            stats.top_speed = speed;
+        }
+    }
+}
+
+fn track_thrusting(
+    sim: Res<SimStep>,
+    game_paused: Res<GamePaused>,
+    player_died: Res<PlayerDied>,
+    mut stats: ResMut<RunStats>,
+    player_query: Query<&Thrusting, With<Player>>,
+) {
+    if game_paused.0 || player_died.0 {
+        return;
+    }
+
+    // In co-op this adds up both players' thrusting, like hits_taken does with their hits
+    let thrusting = player_query.iter().filter(|thrusting| thrusting.0).count();
+    stats.time_thrusting += sim.delta_seconds() * thrusting as f32;
+}
+
+fn track_spawns(
+    mut stats: ResMut<RunStats>,
+    chaser_query: Query<(&SizeScale, Option<&Chicken>), (Added<ChasingEnemy>, Without<Resumed>)>,
+) {
+    for (SizeScale(size_scale), chicken) in chaser_query.iter() {
+        let spawns = &mut stats.spawns;
+        if chicken.is_some() {
+            spawns.chickens += 1;
+        } else if *size_scale < SMALL_SIZE_SCALE {
+            spawns.small += 1;
+        } else if *size_scale < LARGE_SIZE_SCALE {
+            spawns.medium += 1;
+        } else {
+            spawns.large += 1;
+        }
+    }
+}
+
+fn track_health_time(
+    sim: Res<SimStep>,
+    game_paused: Res<GamePaused>,
+    player_died: Res<PlayerDied>,
+    mut stats: ResMut<RunStats>,
+    health_query: Query<&PlayerHealth, With<Player>>,
+) {
+    if game_paused.0 || player_died.0 {
+        return;
+    }
+
+    for health in health_query.iter() {
+        let health = (health.0 as usize).min(stats.time_at_health.len() - 1);
+        stats.time_at_health[health] += sim.delta_seconds();
+    }
+}
+
+// Writes the finished run out so it can be looked at (or shared) outside the game
+fn export_stats(
+    player_died: Res<PlayerDied>,
+    stats: Res<RunStats>,
+) {
+    if player_died.is_changed() && player_died.0 {
+        persistence::save(LAST_RUN_FILE, &*stats);
+    }
+}
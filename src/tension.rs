@@ -0,0 +1,67 @@
+use bevy::prelude::*;
+
+use crate::player::MAX_HEALTH;
+use crate::types::*;
+
+// Asteroids closer than this to the player count as nearby
+pub const NEARBY_RADIUS: f32 = 500.0;
+// Each input maxes out its share of the tension at these values
+const FULL_TENSION_CHASERS: f32 = 300.0;
+const FULL_TENSION_NEARBY: f32 = 25.0;
+
+const CHASER_WEIGHT: f32 = 0.3;
+const NEARBY_WEIGHT: f32 = 0.45;
+const HEALTH_WEIGHT: f32 = 0.25;
+
+// How quickly the tension follows the raw metric. Lower is smoother.
+const TENSION_RESPONSE: f32 = 1.5;
+
+pub struct TensionPlugin;
+
+impl Plugin for TensionPlugin {
+    fn build(&self, app: &mut App) {
+        app
+            .add_system_set(SystemSet::on_update(AppState::InGame).with_system(update_tension.label("update_tension")));
+    }
+}
+
+fn update_tension(
+    time: Res<Time>,
+    game_paused: Res<GamePaused>,
+    player_died: Res<PlayerDied>,
+    chaser_count: Res<ChaserCount>,
+    mut tension: ResMut<Tension>,
+    player_query: Query<(&Transform, &PlayerHealth), With<Player>>,
+    enemy_query: Query<&Transform, (With<ChasingEnemy>, Without<Player>)>,
+) {
+    if game_paused.0 {
+        return;
+    }
+
+    // Downed players don't count, since the asteroids aren't after them
+    let living: Vec<(Vec2, u8)> = player_query
+        .iter()
+        .filter(|(_, health)| health.0 > 0)
+        .map(|(transform, health)| (transform.translation.truncate(), health.0))
+        .collect();
+    let health = living.iter().map(|(_, health)| *health).min().unwrap_or(0);
+
+    tension.nearby_enemies = enemy_query
+        .iter()
+        .filter(|t| living.iter().any(|(position, _)| t.translation.truncate().distance(*position) < NEARBY_RADIUS))
+        .count();
+
+    let target =
+        if player_died.0 {
+            0.0
+        } else {
+            let chasers = (chaser_count.current as f32 / FULL_TENSION_CHASERS).min(1.0);
+            let nearby = (tension.nearby_enemies as f32 / FULL_TENSION_NEARBY).min(1.0);
+            let damage = 1.0 - (health as f32 / MAX_HEALTH as f32).min(1.0);
+
+            chasers * CHASER_WEIGHT + nearby * NEARBY_WEIGHT + damage * HEALTH_WEIGHT
+        };
+
+    let blend = (TENSION_RESPONSE * time.delta_seconds()).min(1.0);
+    tension.value += (target - tension.value) * blend;
+}
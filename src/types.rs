@@ -1,65 +1,131 @@
+use std::collections::HashMap;
+use std::net::SocketAddr;
+use std::path::PathBuf;
+use std::time::Duration;
+
 use bevy::prelude::*;
 use heron::PhysicsLayer;
+use serde::{Deserialize, Serialize};
 
-// A tag to identify the player entity
+/// A tag to identify the player entities (the earths). There are two of them in co-op.
 #[derive(Component)]
 pub struct Player;
 
+/// Which player an earth, ship or heart belongs to. 0 is player one.
+#[derive(Component, Clone, Copy, PartialEq)]
+pub struct PlayerId(pub usize);
+
+/// Player one's earth and ship, which exist for the whole game. Run stats, replays and saves follow this player.
+#[derive(Component)]
+pub struct PlayerOne;
+
+/// Where a player's movement comes from
+#[derive(Component)]
+pub struct PlayerInput {
+    /// None follows the controls picked in the options
+    pub controls: Option<ControlScheme>,
+    /// Index of the gamepad that can also move this player
+    pub gamepad: usize,
+    /// Set in online games, where movement comes from the lockstep session instead of the local keyboard and gamepad.
+    /// Holds the direction for the current tick.
+    pub network: Option<Vec2>,
+}
+
+/// A player with no health left is down until the other player stays close enough for long enough to revive them.
+/// This is how far into the revive they are, in seconds.
+#[derive(Component, Default)]
+pub struct ReviveProgress(pub f32);
+
+/// How many players the next run starts with, 1 or 2. Picked on the main menu.
+pub struct PlayerCount(pub usize);
+
+/// The ship sprite drawn over the earth, turned to face the way the player is moving
 #[derive(Component)]
 pub struct PlayerShip;
 
-// Used to track the player's current health
+/// Whether the player is holding any movement keys this frame
+#[derive(Component)]
+pub struct Thrusting(pub bool);
+
+/// Used to track the player's current health
 #[derive(Component)]
 pub struct PlayerHealth(pub u8);
 
-// The u8 represents the placement of the heart
+/// The u8 represents the placement of the heart. Hearts also have the PlayerId of the player they belong to.
 #[derive(Component)]
 pub struct HeartSprite(pub u8);
 
+/// An asteroid (or chicken) chasing the player
 #[derive(Component)]
 pub struct ChasingEnemy;
 
+/// Marks entities that only belong to the current run. They are all despawned when a run starts or ends.
 #[derive(Component)]
-pub struct Speed(pub f32);
+pub struct RunScoped;
 
+/// The rare chaser that uses the chicken sprite instead of a meteor
+#[derive(Component)]
+pub struct Chicken;
 
+/// Added to a chicken once it has been on screen, so it is only counted once
+#[derive(Component)]
+pub struct Spotted;
+
+/// A chaser brought back by Continue rather than spawned this run, so it isn't counted as a new spawn
+#[derive(Component)]
+pub struct Resumed;
+
+/// Velocity added each frame, by the player's input or by a chaser's pull towards the player
+#[derive(Component)]
+pub struct Speed(pub f32);
 
-// Define your physics layers
-// Probably only need one or none
+/// Collision layers for heron. The player and asteroids only collide with each other.
 #[derive(PhysicsLayer)]
 pub enum Layer {
     Player,
     Enemies,
 }
 
+/// Time until the next asteroid spawn. The interval comes from the Difficulty.
 pub struct SpawnTimer(pub Timer);
 
+/// Set once every player is down, which ends the run, until the next run starts
 pub struct PlayerDied(pub bool);
 
+/// Toggled by the dev console's god command. Hits don't cost health while it's on.
+pub struct GodMode(pub bool);
+
+/// A chaser's size relative to the base asteroid size
 #[derive(Component)]
 pub struct SizeScale(pub f32);
 
-// A unit pub struct to help identify the FPS UI component, since there may be many Text components
+/// A unit struct to help identify the FPS UI component, since there may be many Text components
 #[derive(Component)]
 pub struct FpsText;
 
+/// The HUD text showing how many asteroids are alive
 #[derive(Component)]
 pub struct EnemyCountText;
 
+/// Big message text in the middle of the screen
 #[derive(Component)]
 pub struct CenterMessageText;
 
+/// Smaller message text under CenterMessageText
 #[derive(Component)]
 pub struct SubCenterText;
 
-// A unit struct to help identify the color-changing Text component
+/// A unit struct to help identify the color-changing Text component
 #[derive(Component)]
 pub struct ColorText;
 
+/// Marks the world camera, as opposed to the UI camera
 #[derive(Component)]
 pub struct Camera2D;
 
+/// Whether gameplay is paused. Systems that change the game should check this first.
 pub struct GamePaused(pub bool);
+/// Live asteroids, and the most that are allowed at once
 pub struct ChaserCount {
     pub current: u32,
     pub max: u32,
@@ -78,10 +144,672 @@ impl ChaserCount {
     }
 }
 
+/// How many times spawned asteroids have grown this run
 pub struct SpawnSizeIncrements(pub u8);
+/// Time until spawned asteroids grow again
 pub struct IncreaseSpawnSizeTimer(pub Timer);
+/// Heart shown for each point of health the player has
 pub struct FullHeartSprite(pub Handle<Image>);
+/// Heart shown for each point of health the player has lost
 pub struct EmptyHeartSprite(pub Handle<Image>);
+/// The meteor texture used by most chasers
 pub struct ChaserSprite(pub Handle<Image>);
-pub struct RandomGenerator(pub rand::rngs::StdRng);
-pub struct ChickenSprite(pub Handle<Image>);
\ No newline at end of file
+/// Random numbers for gameplay, seeded from GameSeed so a run can be repeated.
+/// ChaCha12 is the same generator as StdRng, but its position in the stream can be read back for saved runs.
+pub struct RandomGenerator(pub rand_chacha::ChaCha12Rng);
+
+/// The seed RandomGenerator was created from at the start of the current run
+pub struct GameSeed(pub u64);
+
+/// A seed to use for every run instead of a random one, set through EarthEscapeBuilder::seed
+pub struct SeedOverride(pub Option<u64>);
+
+/// How far the game simulation moves this frame. Systems that change the game should use this instead of `Time`.
+/// Offline it's just the frame time. Online the game steps in fixed ticks, and stands still while waiting for the other player's input.
+#[derive(Default)]
+pub struct SimStep {
+    pub delta: Duration,
+    /// Set while an online session is stepping the game
+    pub lockstep: bool,
+}
+
+impl SimStep {
+    pub fn delta_seconds(&self) -> f32 {
+        self.delta.as_secs_f32()
+    }
+
+    /// True on frames where the game shouldn't move at all
+    pub fn is_stalled(&self) -> bool {
+        self.delta == Duration::ZERO
+    }
+}
+
+/// Which end of an online game this instance is
+#[derive(Debug, Clone, PartialEq)]
+pub enum NetRole {
+    /// Waits for the other player to connect, and picks the seed and difficulty
+    Host,
+    /// Connects to a host at this address
+    Join(SocketAddr),
+}
+
+/// Settings for online play, set through EarthEscapeBuilder::network. Each instance controls one of the two co-op players.
+#[derive(Debug, Clone)]
+pub struct NetConfig {
+    pub role: NetRole,
+    /// Local UDP port. 0 lets the system pick one, which is fine when joining.
+    pub port: u16,
+    /// Extra delay added to every outgoing packet, in milliseconds, to test bad connections
+    pub latency_ms: u32,
+    /// Chance of dropping each outgoing packet, from 0.0 to 1.0, to test bad connections
+    pub packet_loss: f32,
+}
+
+impl NetConfig {
+    pub fn host(port: u16) -> Self {
+        NetConfig {
+            role: NetRole::Host,
+            port,
+            latency_ms: 0,
+            packet_loss: 0.0,
+        }
+    }
+
+    pub fn join(address: SocketAddr) -> Self {
+        NetConfig {
+            role: NetRole::Join(address),
+            port: 0,
+            latency_ms: 0,
+            packet_loss: 0.0,
+        }
+    }
+}
+
+/// The texture used by chicken chasers
+pub struct ChickenSprite(pub Handle<Image>);
+/// Sent by the score plugin when a chaser passes close to the player without touching it
+pub struct NearMissEvent {
+    pub enemy: Entity,
+    pub position: Vec3,
+    pub relative_speed: f32,
+    pub size_scale: f32,
+}
+
+/// Consecutive near misses without taking damage. Each one raises the bonus multiplier.
+#[derive(Default)]
+pub struct NearMissChain {
+    pub count: u32,
+}
+
+impl NearMissChain {
+    pub fn multiplier(&self) -> f32 {
+        (1.0 + self.count.saturating_sub(1) as f32 * 0.5).min(5.0)
+    }
+}
+
+/// How the fixed size world view maps onto the actual window. Updated whenever the window is resized.
+pub struct ViewFit {
+    /// World units per window pixel at zoom 1.0
+    pub world_per_pixel: f32,
+    /// Multiplier for HUD sizes, 1.0 at the reference resolution
+    pub hud_scale: f32,
+}
+
+/// Screen shake amount, from 0.0 (still) to 1.0 (maximum shake). Decays over time.
+pub struct CameraTrauma(pub f32);
+
+impl CameraTrauma {
+    pub fn add(&mut self, amount: f32) {
+        self.0 = (self.0 + amount).clamp(0.0, 1.0);
+    }
+}
+
+/// Lets players tone down or turn off camera motion
+#[derive(Serialize, Deserialize, Clone, Copy)]
+#[serde(default)]
+pub struct CameraSettings {
+    /// Multiplier for screen shake, 0.0 disables it
+    pub shake_scale: f32,
+    pub smooth_follow: bool,
+    pub look_ahead: bool,
+    pub dynamic_zoom: bool,
+}
+
+impl Default for CameraSettings {
+    fn default() -> Self {
+        CameraSettings {
+            shake_scale: 1.0,
+            smooth_follow: true,
+            look_ahead: true,
+            dynamic_zoom: true,
+        }
+    }
+}
+
+/// How much of the world the radar shows
+pub struct RadarSettings {
+    /// World distance from the player that the edge of the radar covers
+    pub range: f32,
+    pub max_blips: usize,
+}
+
+impl Default for RadarSettings {
+    fn default() -> Self {
+        RadarSettings {
+            range: 3000.0,
+            max_blips: 400,
+        }
+    }
+}
+
+/// Anything with this component shows up on the radar. Asteroids are found through ChasingEnemy instead.
+#[derive(Component)]
+pub enum RadarTarget {
+    Pickup,
+    Objective,
+}
+
+/// Volumes go from 0.0 to 1.0
+#[derive(Serialize, Deserialize, Clone, Copy)]
+#[serde(default)]
+pub struct AudioVolumes {
+    pub master: f32,
+    pub music: f32,
+    pub sfx: f32,
+}
+
+impl Default for AudioVolumes {
+    fn default() -> Self {
+        AudioVolumes {
+            master: 1.0,
+            music: 0.6,
+            sfx: 0.8,
+        }
+    }
+}
+
+impl AudioVolumes {
+    /// The volume actually used for the music channel, after the master volume is applied
+    pub fn music(&self) -> f32 {
+        self.master * self.music
+    }
+
+    pub fn sfx(&self) -> f32 {
+        self.master * self.sfx
+    }
+}
+
+/// How dangerous things are right now, from 0.0 (calm) to 1.0 (chaos). Recomputed every tick by the tension plugin.
+#[derive(Default)]
+pub struct Tension {
+    pub value: f32,
+    /// Asteroids within tension::NEARBY_RADIUS of the player
+    pub nearby_enemies: usize,
+}
+
+/// The game's screens. InGame is pushed over by Options and Replay so the run isn't lost.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum AppState {
+    MainMenu,
+    HighScores,
+    Options,
+    InGame,
+    Replay,
+}
+
+/// The menu's Difficulty while a run plays at another one, like a ghost race or a continued save. Put back when the run ends.
+pub struct ReplacedDifficulty(pub Option<Difficulty>);
+
+/// Picked on the main menu. Changes spawn rate, asteroid speed and the score multiplier.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Difficulty {
+    Easy,
+    Normal,
+    Hard,
+}
+
+impl Difficulty {
+    pub fn next(self) -> Self {
+        match self {
+            Difficulty::Easy => Difficulty::Normal,
+            Difficulty::Normal => Difficulty::Hard,
+            Difficulty::Hard => Difficulty::Easy,
+        }
+    }
+
+    /// Translation key for the difficulty's name
+    pub fn key(self) -> &'static str {
+        match self {
+            Difficulty::Easy => "difficulty.easy",
+            Difficulty::Normal => "difficulty.normal",
+            Difficulty::Hard => "difficulty.hard",
+        }
+    }
+
+    /// Seconds between asteroid spawns
+    pub fn spawn_interval(self) -> f32 {
+        match self {
+            Difficulty::Easy => 0.8,
+            Difficulty::Normal => 0.5,
+            Difficulty::Hard => 0.35,
+        }
+    }
+
+    pub fn chaser_speed(self) -> f32 {
+        match self {
+            Difficulty::Easy => 2.0,
+            Difficulty::Normal => 2.5,
+            Difficulty::Hard => 3.0,
+        }
+    }
+
+    pub fn score_multiplier(self) -> f32 {
+        match self {
+            Difficulty::Easy => 0.5,
+            Difficulty::Normal => 1.0,
+            Difficulty::Hard => 2.0,
+        }
+    }
+}
+
+/// Sent to reset everything for a fresh run, both when leaving the main menu and when restarting after death
+pub struct StartRunEvent;
+
+/// Sent when the player asks to restart. Offline this becomes a StartRunEvent straight away,
+/// online the restart goes out with the next tick so both players restart on the same one.
+pub struct RestartRequestEvent;
+
+/// Points and time survived for the current run
+pub struct Score {
+    pub points: f32,
+    pub survival_time: f32,
+}
+
+/// Survival time when each score milestone was reached this run, which is how runs are compared with a ghost
+#[derive(Default, Clone, Serialize, Deserialize)]
+pub struct ScoreMilestones(pub Vec<f32>);
+
+/// One finished run on the high score table
+#[derive(Serialize, Deserialize, Clone)]
+pub struct HighScoreEntry {
+    pub score: u32,
+    pub survival_time: f32,
+    pub difficulty: Difficulty,
+}
+
+/// Best runs first. Saved to the config directory whenever a run ends.
+#[derive(Serialize, Deserialize, Default)]
+pub struct HighScores(pub Vec<HighScoreEntry>);
+
+/// The font used for every bit of text. Changes with the language.
+pub struct BoldFont(pub Handle<Font>);
+
+/// What a menu button does when pressed
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum MenuAction {
+    Start,
+    StartCoop,
+    Continue,
+    RaceGhost,
+    CycleDifficulty,
+    Options,
+    HighScores,
+    Quit,
+    Back,
+    ChangeSetting(SettingKind),
+    Resume,
+    Restart,
+    QuitToMenu,
+    ViewReplay,
+}
+
+/// The index is the button's place in keyboard/gamepad navigation order
+#[derive(Component)]
+pub struct MenuButton {
+    pub index: usize,
+    pub action: MenuAction,
+    pub enabled: bool,
+}
+
+/// Index of the menu button that keyboard and gamepad input acts on
+pub struct MenuFocus(pub usize);
+
+/// Sent when a menu button is pressed, by mouse, keyboard or gamepad
+pub struct MenuActionEvent(pub MenuAction);
+
+/// The root node of a menu screen, despawned along with all its children when the screen closes
+#[derive(Component)]
+pub struct MenuScreen;
+
+/// Which keys move the player
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ControlScheme {
+    WasdAndArrows,
+    Wasd,
+    Arrows,
+}
+
+impl ControlScheme {
+    /// Translation key for the scheme's name
+    pub fn key(self) -> &'static str {
+        match self {
+            ControlScheme::WasdAndArrows => "controls.wasd_and_arrows",
+            ControlScheme::Wasd => "controls.wasd",
+            ControlScheme::Arrows => "controls.arrows",
+        }
+    }
+
+    pub fn next(self) -> Self {
+        match self {
+            ControlScheme::WasdAndArrows => ControlScheme::Wasd,
+            ControlScheme::Wasd => ControlScheme::Arrows,
+            ControlScheme::Arrows => ControlScheme::WasdAndArrows,
+        }
+    }
+
+    /// Checks whichever of the two keys for a direction this scheme uses
+    pub fn pressed(self, input: &Input<KeyCode>, letter_key: KeyCode, arrow_key: KeyCode) -> bool {
+        match self {
+            ControlScheme::WasdAndArrows => input.pressed(letter_key) || input.pressed(arrow_key),
+            ControlScheme::Wasd => input.pressed(letter_key),
+            ControlScheme::Arrows => input.pressed(arrow_key),
+        }
+    }
+}
+
+/// Everything on the options screen. Loaded before the window is created and saved to settings.ron whenever it changes.
+#[derive(Serialize, Deserialize, Clone)]
+#[serde(default)]
+pub struct Settings {
+    pub fullscreen: bool,
+    pub window_width: f32,
+    pub window_height: f32,
+    pub volumes: AudioVolumes,
+    pub camera: CameraSettings,
+    pub controls: ControlScheme,
+    pub language: Language,
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Settings {
+            fullscreen: false,
+            window_width: 1200.,
+            window_height: 800.,
+            volumes: AudioVolumes::default(),
+            camera: CameraSettings::default(),
+            controls: ControlScheme::WasdAndArrows,
+            language: Language::English,
+        }
+    }
+}
+
+/// One row on the options screen
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SettingKind {
+    Fullscreen,
+    WindowSize,
+    MasterVolume,
+    MusicVolume,
+    SfxVolume,
+    CameraShake,
+    CameraMotion,
+    Controls,
+    Language,
+}
+
+/// How many asteroids of each kind were spawned during a run. Sizes are bucketed by SizeScale.
+#[derive(Default, Clone, Serialize, Deserialize)]
+pub struct SpawnCounts {
+    pub small: u32,
+    pub medium: u32,
+    pub large: u32,
+    pub chickens: u32,
+}
+
+/// Where the player was last frame, for the distance stat
+pub struct LastPlayerPosition(pub Option<Vec2>);
+
+/// Numbers about the current run, reset whenever a run starts and written out to last_run.ron when it ends
+#[derive(Default, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct RunStats {
+    pub peak_enemies: u32,
+    /// Most chasers inside the camera's view at once
+    pub peak_on_screen: u32,
+    pub distance: f32,
+    pub top_speed: f32,
+    /// Added up over every player in co-op, like hits_taken
+    pub time_thrusting: f32,
+    pub hits_taken: u32,
+    pub near_misses: u32,
+    pub chickens_spotted: u32,
+    pub spawns: SpawnCounts,
+    /// Seconds spent at each health value, indexed by PlayerHealth. Each player counts separately in co-op.
+    pub time_at_health: [f32; 6],
+}
+
+/// Which sprite a chaser is drawn with
+#[derive(Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum ChaserKind {
+    Meteor,
+    Chicken,
+}
+
+/// Tells chasers apart in a replay. Entity ids get reused once a chaser is despawned, these don't.
+#[derive(Component, Clone, Copy)]
+pub struct ChaserId(pub u32);
+
+/// The ChaserId the next chaser gets, counted from 0 each run
+pub struct NextChaserId(pub u32);
+
+/// One asteroid in a replay frame
+#[derive(Clone, Serialize, Deserialize)]
+pub struct ReplayEnemy {
+    /// The chaser's ChaserId, used to match the same asteroid between frames
+    pub id: u32,
+    pub position: Vec2,
+    pub size: f32,
+    pub kind: ChaserKind,
+}
+
+/// The player and nearby asteroids at one point in a run
+#[derive(Clone, Serialize, Deserialize)]
+pub struct ReplayFrame {
+    pub time: f32,
+    pub player_position: Vec2,
+    pub ship_rotation: f32,
+    /// Sorted by id
+    pub enemies: Vec<ReplayEnemy>,
+}
+
+/// Snapshots of the current run, recorded a few times a second for the replay viewer
+#[derive(Default, Clone, Serialize, Deserialize)]
+pub struct RunRecording {
+    pub frames: Vec<ReplayFrame>,
+}
+
+/// One asteroid in a saved run
+#[derive(Serialize, Deserialize)]
+pub struct SavedChaser {
+    pub position: Vec3,
+    pub rotation: Quat,
+    pub linear_velocity: Vec3,
+    pub angular_velocity: Vec3,
+    pub size_scale: f32,
+    pub speed: f32,
+    pub kind: ChaserKind,
+    /// Chickens only, whether it has already been counted in the run stats
+    pub spotted: bool,
+    /// Keeps the chaser matched up with itself in the run's replay
+    pub id: u32,
+}
+
+/// One point on a ghost's trail
+#[derive(Clone, Copy, Serialize, Deserialize)]
+pub struct GhostPoint {
+    /// Survival time
+    pub time: f32,
+    pub position: Vec2,
+    pub ship_rotation: f32,
+}
+
+/// A finished run to race against. The best run is kept in ghost.ron, which can also be passed around as a file.
+/// `version` is bumped whenever the layout changes, and ghosts from other versions are ignored.
+#[derive(Clone, Serialize, Deserialize)]
+pub struct GhostRun {
+    pub version: u32,
+    pub seed: u64,
+    pub difficulty: Difficulty,
+    pub points: f32,
+    pub survival_time: f32,
+    /// Survival time when each score milestone was reached
+    pub milestones: Vec<f32>,
+    /// The player's path, taken from the run's replay recording
+    pub trail: Vec<GhostPoint>,
+}
+
+/// The ghost that the main menu's Race Ghost button races against, if there is one
+pub struct LoadedGhost(pub Option<GhostRun>);
+
+/// A ghost file (like one from a friend) to race instead of the best run saved on this machine, set through EarthEscapeBuilder::ghost_file
+pub struct GhostFile(pub Option<PathBuf>);
+
+/// A run in progress, written to saved_run.ron when the player quits to the menu and read back by Continue.
+/// `version` is bumped whenever the layout changes, and saves from other versions are thrown away.
+#[derive(Serialize, Deserialize)]
+pub struct SavedRun {
+    pub version: u32,
+    pub difficulty: Difficulty,
+    pub seed: u64,
+    /// The RandomGenerator's key and how far through its stream it had got
+    pub rng_seed: [u8; 32],
+    pub rng_word_pos: u64,
+    pub player_position: Vec3,
+    pub player_velocity: Vec3,
+    pub health: u8,
+    pub thrusting: bool,
+    pub ship_position: Vec3,
+    pub ship_rotation: Quat,
+    pub chasers: Vec<SavedChaser>,
+    pub next_chaser_id: u32,
+    pub chaser_max: u32,
+    pub spawn_interval: f32,
+    /// Seconds into the current spawn interval
+    pub spawn_elapsed: f32,
+    pub size_increase_elapsed: f32,
+    pub size_increments: u8,
+    pub points: f32,
+    pub survival_time: f32,
+    pub near_miss_chain: u32,
+    pub stats: RunStats,
+    pub milestones: ScoreMilestones,
+    /// So the resumed run still has a complete replay, and can become the ghost
+    pub recording: RunRecording,
+}
+
+/// Languages the UI can be shown in
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Language {
+    English,
+    Spanish,
+    German,
+    Russian,
+}
+
+impl Language {
+    /// Language files are found at assets/lang/<code>.lang
+    pub fn code(self) -> &'static str {
+        match self {
+            Language::English => "en",
+            Language::Spanish => "es",
+            Language::German => "de",
+            Language::Russian => "ru",
+        }
+    }
+
+    pub fn next(self) -> Self {
+        match self {
+            Language::English => Language::Spanish,
+            Language::Spanish => Language::German,
+            Language::German => Language::Russian,
+            Language::Russian => Language::English,
+        }
+    }
+}
+
+/// The strings for the current language, with English behind them for anything missing
+#[derive(Default)]
+pub struct Locale {
+    pub strings: HashMap<String, String>,
+    pub fallback: HashMap<String, String>,
+}
+
+impl Locale {
+    /// Falls back to English, then to the key itself so missing strings are easy to spot
+    pub fn get<'a>(&'a self, key: &'a str) -> &'a str {
+        self.strings
+            .get(key)
+            .or_else(|| self.fallback.get(key))
+            .map(|value| value.as_str())
+            .unwrap_or(key)
+    }
+
+    /// Fills in {name} placeholders in the translated string
+    pub fn format(&self, key: &str, args: &[(&str, String)]) -> String {
+        let mut value = self.get(key).to_string();
+        for (name, arg) in args {
+            value = value.replace(&format!("{{{}}}", name), arg);
+        }
+        value
+    }
+}
+
+/// Text that is rewritten from the locale whenever the language changes
+#[derive(Component)]
+pub struct LocalizedText {
+    pub key: &'static str,
+    pub section: usize,
+}
+
+impl LocalizedText {
+    pub fn new(key: &'static str) -> Self {
+        LocalizedText {
+            key,
+            section: 0,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn locale(strings: &[(&str, &str)], fallback: &[(&str, &str)]) -> Locale {
+        let to_map = |pairs: &[(&str, &str)]| pairs.iter().map(|(key, value)| (key.to_string(), value.to_string())).collect();
+        Locale {
+            strings: to_map(strings),
+            fallback: to_map(fallback),
+        }
+    }
+
+    #[test]
+    fn format_fills_in_every_placeholder() {
+        let locale = locale(&[("replay.time", "{time}s of {length}s ({time})")], &[]);
+        let text = locale.format("replay.time", &[("time", "1.5".to_string()), ("length", "30.0".to_string())]);
+        assert_eq!(text, "1.5s of 30.0s (1.5)");
+    }
+
+    #[test]
+    fn format_leaves_unknown_placeholders_alone() {
+        let locale = locale(&[("score", "Score: {points}")], &[]);
+        assert_eq!(locale.format("score", &[("time", "2".to_string())]), "Score: {points}");
+    }
+
+    #[test]
+    fn format_falls_back_to_english_then_the_key() {
+        let locale = locale(&[], &[("score", "Score: {points}")]);
+        assert_eq!(locale.format("score", &[("points", "12".to_string())]), "Score: 12");
+        assert_eq!(locale.format("missing.key", &[("points", "12".to_string())]), "missing.key");
+    }
+}
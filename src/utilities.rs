@@ -1,4 +1,5 @@
-use heron::CollisionLayers;
+use bevy::prelude::*;
+use heron::{CollisionLayers, CollisionShape};
 use crate::types::Layer;
 
 pub fn is_player(layers: CollisionLayers) -> bool {
@@ -7,4 +8,85 @@ pub fn is_player(layers: CollisionLayers) -> bool {
 
 pub fn is_enemy(layers: CollisionLayers) -> bool {
     !layers.contains_group(Layer::Player) && layers.contains_group(Layer::Enemies)
-}
\ No newline at end of file
+}
+
+// Critically damped spring, adapted from the SmoothDamp function in Game Programming Gems 4 (chapter 1.10).
+// `velocity` is carried between calls so the motion stays continuous when the target moves.
+pub fn smooth_damp(current: Vec3, target: Vec3, velocity: &mut Vec3, smooth_time: f32, delta: f32) -> Vec3 {
+    let smooth_time = smooth_time.max(0.0001);
+    let omega = 2.0 / smooth_time;
+    let x = omega * delta;
+    let exp = 1.0 / (1.0 + x + 0.48 * x * x + 0.235 * x * x * x);
+    let change = current - target;
+    let temp = (*velocity + omega * change) * delta;
+    *velocity = (*velocity - omega * temp) * exp;
+    target + (change + temp) * exp
+}
+
+pub fn smooth_damp_f32(current: f32, target: f32, velocity: &mut f32, smooth_time: f32, delta: f32) -> f32 {
+    let mut velocity_3d = Vec3::new(*velocity, 0.0, 0.0);
+    let result = smooth_damp(Vec3::new(current, 0.0, 0.0), Vec3::new(target, 0.0, 0.0), &mut velocity_3d, smooth_time, delta);
+    *velocity = velocity_3d.x;
+    result.x
+}
+
+pub fn sphere_radius(shape: &CollisionShape) -> f32 {
+    match shape {
+        CollisionShape::Sphere { radius } => *radius,
+        _ => 0.0,
+    }
+}
+
+// The middle of the given positions. Outside of co-op that's just the player's position.
+pub fn center_of(positions: impl Iterator<Item = Vec2>) -> Option<Vec2> {
+    let (sum, count) = positions.fold((Vec2::ZERO, 0), |(sum, count), position| (sum + position, count + 1));
+    if count == 0 {
+        None
+    } else {
+        Some(sum / count as f32)
+    }
+}
+
+pub fn nearest(position: Vec2, candidates: impl Iterator<Item = Vec2>) -> Option<Vec2> {
+    candidates.min_by(|a, b| {
+        a.distance_squared(position)
+            .partial_cmp(&b.distance_squared(position))
+            .unwrap_or(std::cmp::Ordering::Equal)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn smooth_damp_settles_on_the_target() {
+        let target = Vec3::new(100.0, -50.0, 0.0);
+        let mut position = Vec3::ZERO;
+        let mut velocity = Vec3::ZERO;
+        for _ in 0..600 {
+            position = smooth_damp(position, target, &mut velocity, 0.3, 1.0 / 60.0);
+        }
+        assert!(position.distance(target) < 0.01);
+        assert!(velocity.length() < 0.01);
+    }
+
+    #[test]
+    fn smooth_damp_approaches_without_overshooting() {
+        let mut position = 0.0;
+        let mut velocity = 0.0;
+        for _ in 0..120 {
+            let next = smooth_damp_f32(position, 10.0, &mut velocity, 0.25, 1.0 / 60.0);
+            assert!(next >= position && next <= 10.0 + 1e-4);
+            position = next;
+        }
+    }
+
+    #[test]
+    fn smooth_damp_stays_put_without_time_passing() {
+        let mut velocity = Vec3::ZERO;
+        let position = smooth_damp(Vec3::new(3.0, 4.0, 0.0), Vec3::new(20.0, 0.0, 0.0), &mut velocity, 0.3, 0.0);
+        assert!(position.distance(Vec3::new(3.0, 4.0, 0.0)) < 1e-4);
+        assert_eq!(velocity, Vec3::ZERO);
+    }
+}